@@ -0,0 +1,106 @@
+//! An ordered multiset built on 'BinaryTree'.
+
+use std::cmp::Ordering;
+
+use super::BinaryTree;
+use super::BinaryTree::*;
+
+/// A value and how many times it has been inserted. Only the value takes part in the ordering.
+struct Counted<T> {
+	value: T,
+	count: usize,
+}
+
+impl<T: Ord> PartialEq for Counted<T> {
+	fn eq(&self, other: &Counted<T>) -> bool { self.value == other.value }
+}
+impl<T: Ord> Eq for Counted<T> {}
+impl<T: Ord> PartialOrd for Counted<T> {
+	fn partial_cmp(&self, other: &Counted<T>) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+impl<T: Ord> Ord for Counted<T> {
+	fn cmp(&self, other: &Counted<T>) -> Ordering { self.value.cmp(&other.value) }
+}
+
+/// A search tree that keeps count of duplicates instead of dropping them, so each distinct
+/// value is stored once along with the number of times it was added.
+pub struct CountingTree<T> {
+	tree: BinaryTree<Counted<T>>,
+	total: usize,
+}
+
+impl<T: Ord> CountingTree<T> {
+	pub fn new() -> CountingTree<T> {
+		CountingTree { tree: Empty, total: 0 }
+	}
+
+	/// Add one occurrence of 'value'.
+	pub fn add(&mut self, value: T) {
+		self.tree.insert_ref(Counted { value, count: 0 }).count += 1;
+		self.total += 1;
+	}
+
+	/// Return how many times 'value' has been added, which is zero if it never was.
+	pub fn count_of(&self, value: &T) -> usize {
+		let mut tree = &self.tree;
+		while let NonEmpty(ref node) = *tree {
+			tree = match value.cmp(&node.element.value) {
+				Ordering::Less => &node.left,
+				Ordering::Greater => &node.right,
+				Ordering::Equal => return node.element.count,
+			};
+		}
+		0
+	}
+
+	/// The number of distinct values.
+	pub fn distinct_len(&self) -> usize {
+		self.tree.iter().count()
+	}
+
+	/// The number of values added, counting every duplicate.
+	pub fn total_len(&self) -> usize {
+		self.total
+	}
+
+	/// Iterate over the distinct values in order, each paired with its count.
+	pub fn counts(&self) -> impl Iterator<Item = (&T, usize)> {
+		self.tree.iter().map(|counted| (&counted.value, counted.count))
+	}
+
+	/// Iterate over the distinct values in order, each once however often it was added.
+	pub fn iter(&self) -> impl Iterator<Item = &T> {
+		self.counts().map(|(value, _)| value)
+	}
+}
+
+impl<T: Ord> Default for CountingTree<T> {
+	fn default() -> CountingTree<T> {
+		CountingTree::new()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn counts_duplicates() {
+		let mut tree = CountingTree::new();
+		for &letter in &['a', 'a', 'b', 'a', 'c'] {
+			tree.add(letter);
+		}
+
+		assert_eq!(tree.count_of(&'a'), 3);
+		assert_eq!(tree.count_of(&'b'), 1);
+		assert_eq!(tree.count_of(&'z'), 0);
+		assert_eq!(tree.iter().cloned().collect::<String>(), "abc");
+		assert_eq!(tree.counts().collect::<Vec<_>>(), vec![(&'a', 3), (&'b', 1), (&'c', 1)]);
+		assert_eq!(tree.distinct_len(), 3);
+		assert_eq!(tree.total_len(), 5);
+
+		let empty: CountingTree<char> = CountingTree::default();
+		assert_eq!(empty.total_len(), 0);
+		assert_eq!(empty.iter().next(), None);
+	}
+}
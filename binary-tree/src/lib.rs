@@ -0,0 +1,946 @@
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+
+mod counting;
+mod morris;
+mod threaded;
+
+pub use counting::CountingTree;
+pub use morris::MorrisTree;
+pub use threaded::{ThreadedIter, ThreadedTree};
+
+/// An ordered collection of 'T's.
+pub enum BinaryTree<T> {
+	Empty,
+	NonEmpty(Box<TreeNode<T>>)
+}
+
+/// A part of a BinaryTree.
+pub struct TreeNode<T> {
+	pub element: T,
+	pub left: BinaryTree<T>,
+	pub right: BinaryTree<T>,
+}
+
+use self::BinaryTree::*;
+
+impl<T: Ord> BinaryTree<T> {
+	/// Insert 'value' into the tree, keeping it ordered. Values already present are dropped.
+	pub fn add(&mut self, value: T) {
+		self.insert_ref(value);
+	}
+
+	/// Like 'add', but return a reference to the element now in the tree that compares equal
+	/// to 'value': the new one, or the existing one if 'value' was a duplicate and got dropped.
+	///
+	/// Changing the element through the reference must not change how it is ordered.
+	pub fn insert_ref(&mut self, value: T) -> &mut T {
+		self.insert_counting(value, &mut 0)
+	}
+
+	/// Like 'insert_ref', but add the number of comparisons it makes to 'comparisons'.
+	fn insert_counting(&mut self, value: T, comparisons: &mut usize) -> &mut T {
+		match *self {
+			Empty => {
+				*self = BinaryTree::leaf(value);
+				match *self {
+					NonEmpty(ref mut node) => &mut node.element,
+					Empty => unreachable!(),
+				}
+			}
+			NonEmpty(ref mut node) => {
+				*comparisons += 1;
+				match value.cmp(&node.element) {
+					Ordering::Less => node.left.insert_counting(value, comparisons),
+					Ordering::Greater => node.right.insert_counting(value, comparisons),
+					Ordering::Equal => &mut node.element,
+				}
+			}
+		}
+	}
+
+	/// Build a tree by adding the values of 'iter' in turn, and return it along with the
+	/// number of comparisons that took. Sorted input builds a tree that is one long branch, and
+	/// costs 'n * (n - 1) / 2' comparisons for 'n' values; shuffled input costs more like
+	/// 'n log n'.
+	pub fn from_iter_counting<I: IntoIterator<Item = T>>(iter: I) -> (BinaryTree<T>, usize) {
+		let mut tree = Empty;
+		let mut comparisons = 0;
+		for value in iter {
+			tree.insert_counting(value, &mut comparisons);
+		}
+		(tree, comparisons)
+	}
+
+	/// Count the places where the in-order sequence fails to increase: adjacent pairs whose
+	/// second element is not greater than the first. This is zero for a valid search tree, and
+	/// otherwise says roughly how badly the ordering has been broken.
+	pub fn count_out_of_order(&self) -> usize {
+		self.iter().zip(self.iter().skip(1))
+			.filter(|&(predecessor, successor)| successor <= predecessor)
+			.count()
+	}
+
+	/// Return the elements on the search path from the root down to 'value', ending with
+	/// 'value' itself, or 'None' if the tree doesn't hold it.
+	pub fn path_to(&self, value: &T) -> Option<Vec<&T>> {
+		let mut path = Vec::new();
+		let mut tree = self;
+		while let NonEmpty(ref node) = *tree {
+			path.push(&node.element);
+			tree = match value.cmp(&node.element) {
+				Ordering::Less => &node.left,
+				Ordering::Greater => &node.right,
+				Ordering::Equal => return Some(path),
+			};
+		}
+		None
+	}
+
+	/// Return the ancestor 'k' levels above 'value': its parent for 1, its grandparent for 2,
+	/// and the element equal to 'value' for 0. Return 'None' if 'value' isn't in the tree or
+	/// lies fewer than 'k' levels below the root.
+	pub fn kth_ancestor(&self, value: &T, k: usize) -> Option<&T> {
+		let path = self.path_to(value)?;
+		let depth = path.len() - 1;
+		if k > depth {
+			return None;
+		}
+		Some(path[depth - k])
+	}
+
+	/// Return the subtree whose root holds 'value', or 'None' if the tree doesn't hold it.
+	pub fn subtree(&self, value: &T) -> Option<&BinaryTree<T>> {
+		let mut tree = self;
+		while let NonEmpty(ref node) = *tree {
+			match value.cmp(&node.element) {
+				Ordering::Less => tree = &node.left,
+				Ordering::Greater => tree = &node.right,
+				Ordering::Equal => return Some(tree),
+			}
+		}
+		None
+	}
+
+	/// Add every element of 'iter', dropping those already present, just as 'add' would, but
+	/// by merging them with the tree's elements in order and rebuilding the tree once, as
+	/// balanced as it can be. For large batches this beats adding elements one at a time, which
+	/// can leave the tree lopsided, as well as rebalancing after each one.
+	pub fn extend_balanced<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+		let mut new: Vec<T> = iter.into_iter().collect();
+		// A stable sort keeps the first of several equal new elements ahead of the rest, like
+		// 'add' does.
+		new.sort();
+		new.dedup();
+
+		let mut old = std::mem::replace(self, Empty);
+		let mut merged = Vec::with_capacity(new.len());
+		let mut new = new.into_iter().peekable();
+		while let Some(element) = old.pop_min() {
+			while let Some(smaller) = new.next_if(|value| *value < element) {
+				merged.push(smaller);
+			}
+			new.next_if(|value| *value == element);
+			merged.push(element);
+		}
+		merged.extend(new);
+
+		let len = merged.len();
+		*self = BinaryTree::from_sorted(&mut merged.into_iter(), len);
+	}
+
+	/// Build a balanced tree from the next 'len' elements of 'sorted', which must be in order:
+	/// the first half goes to the left subtree, then the middle element is the root.
+	fn from_sorted<I: Iterator<Item = T>>(sorted: &mut I, len: usize) -> BinaryTree<T> {
+		if len == 0 {
+			return Empty;
+		}
+		let left = BinaryTree::from_sorted(sorted, len / 2);
+		let element = sorted.next().expect("fewer elements than promised");
+		let right = BinaryTree::from_sorted(sorted, len - len / 2 - 1);
+		NonEmpty(Box::new(TreeNode { element, left, right }))
+	}
+
+	/// Repair a search tree in which exactly two elements have been swapped, by swapping
+	/// them back. A tree that is already in order is left alone.
+	///
+	/// The two are found from where the in-order sequence fails to increase: if they were
+	/// next to each other in order, it fails once; otherwise twice, the first time just after
+	/// the larger one and the second time at the smaller. Apart from the traversal itself, this
+	/// takes no extra space.
+	pub fn recover_bst(&mut self) {
+		let mut swapped = None;
+		for (i, (predecessor, successor)) in self.iter().zip(self.iter().skip(1)).enumerate() {
+			if successor < predecessor {
+				let first = swapped.map_or(i, |(first, _)| first);
+				swapped = Some((first, i + 1));
+			}
+		}
+
+		if let Some(positions) = swapped {
+			let mut found = (None, None);
+			self.find_positions_mut(&mut 0, positions, &mut found);
+			if let (Some(first), Some(second)) = found {
+				std::mem::swap(first, second);
+			}
+		}
+	}
+
+	/// Walk the tree in order, counting elements in 'position', and borrow the elements at
+	/// the in-order positions 'wanted' into 'found'.
+	fn find_positions_mut<'a>(&'a mut self,
+							  position: &mut usize,
+							  wanted: (usize, usize),
+							  found: &mut (Option<&'a mut T>, Option<&'a mut T>)) {
+		if let NonEmpty(ref mut node) = *self {
+			let TreeNode { ref mut element, ref mut left, ref mut right } = **node;
+			left.find_positions_mut(position, wanted, found);
+			if *position == wanted.0 {
+				found.0 = Some(element);
+			} else if *position == wanted.1 {
+				found.1 = Some(element);
+			}
+			*position += 1;
+			right.find_positions_mut(position, wanted, found);
+		}
+	}
+
+	/// Return the smallest element, or 'None' if the tree is empty.
+	pub fn min(&self) -> Option<&T> {
+		self.iter().next()
+	}
+
+	/// Return the largest element, or 'None' if the tree is empty.
+	pub fn max(&self) -> Option<&T> {
+		let mut tree = self;
+		let mut largest = None;
+		while let NonEmpty(ref node) = *tree {
+			largest = Some(&node.element);
+			tree = &node.right;
+		}
+		largest
+	}
+}
+
+impl<T> BinaryTree<T> {
+	/// Return the elements of the tree level by level, top to bottom, each level ordered
+	/// left to right.
+	pub fn levels(&self) -> Vec<Vec<&T>> {
+		let mut levels = Vec::new();
+		let mut queue = VecDeque::new();
+		if let NonEmpty(ref node) = *self {
+			queue.push_back(node);
+		}
+
+		while !queue.is_empty() {
+			let mut level = Vec::with_capacity(queue.len());
+			for _ in 0..queue.len() {
+				let node = queue.pop_front().unwrap();
+				level.push(&node.element);
+				for child in &[&node.left, &node.right] {
+					if let NonEmpty(ref child) = **child {
+						queue.push_back(child);
+					}
+				}
+			}
+			levels.push(level);
+		}
+		levels
+	}
+
+	/// Return the element seen at each level when looking at the tree from the right, which
+	/// is the rightmost node of that level even when it lives in a left subtree.
+	pub fn right_side_view(&self) -> Vec<&T> {
+		self.levels().into_iter().filter_map(|level| level.last().cloned()).collect()
+	}
+
+	/// Return the element seen at each level when looking at the tree from the left.
+	pub fn left_side_view(&self) -> Vec<&T> {
+		self.levels().into_iter().filter_map(|level| level.first().cloned()).collect()
+	}
+
+	/// Return the most nodes on any one level, or 0 for the empty tree.
+	///
+	/// This counts only the nodes actually present, not the horizontal extent of the level
+	/// including the gaps between them: a level holding just the leftmost and rightmost of
+	/// four possible positions has width 2, not 4.
+	pub fn width(&self) -> usize {
+		self.levels().iter().map(|level| level.len()).max().unwrap_or(0)
+	}
+
+	/// Return every path from the root down to a leaf, each listing the elements along it from
+	/// the root first. The paths are ordered by their leaves, left to right.
+	pub fn root_to_leaf_paths(&self) -> Vec<Vec<&T>> {
+		let mut paths = Vec::new();
+		self.collect_paths(&mut Vec::new(), &mut paths);
+		paths
+	}
+
+	/// Push onto 'paths' each path from the root to a leaf of this subtree, where 'path' holds
+	/// the elements from the root down to just above it.
+	fn collect_paths<'a>(&'a self, path: &mut Vec<&'a T>, paths: &mut Vec<Vec<&'a T>>) {
+		if let NonEmpty(ref node) = *self {
+			path.push(&node.element);
+			match (&node.left, &node.right) {
+				(&Empty, &Empty) => paths.push(path.clone()),
+				(left, right) => {
+					left.collect_paths(path, paths);
+					right.collect_paths(path, paths);
+				}
+			}
+			path.pop();
+		}
+	}
+
+	/// Return the outline of the tree, going anti-clockwise from the root: down the left
+	/// boundary, along the leaves from left to right, and back up the right boundary. The left
+	/// boundary is the path that keeps to the left child where there is one, and the right
+	/// boundary the reverse; each stops above its leaf, which is listed with the others, so no
+	/// element appears twice.
+	pub fn boundary(&self) -> Vec<&T> {
+		let root = match *self {
+			Empty => return Vec::new(),
+			NonEmpty(ref node) => node,
+		};
+		let mut outline = vec![&root.element];
+		if root.is_leaf() {
+			return outline;
+		}
+
+		let mut tree = &root.left;
+		while let NonEmpty(ref node) = *tree {
+			if node.is_leaf() {
+				break;
+			}
+			outline.push(&node.element);
+			tree = if let NonEmpty(_) = node.left { &node.left } else { &node.right };
+		}
+
+		root.left.collect_leaves(&mut outline);
+		root.right.collect_leaves(&mut outline);
+
+		let mut right_boundary = Vec::new();
+		let mut tree = &root.right;
+		while let NonEmpty(ref node) = *tree {
+			if node.is_leaf() {
+				break;
+			}
+			right_boundary.push(&node.element);
+			tree = if let NonEmpty(_) = node.right { &node.right } else { &node.left };
+		}
+		outline.extend(right_boundary.into_iter().rev());
+		outline
+	}
+
+	/// Push the leaves of this subtree onto 'leaves', left to right.
+	fn collect_leaves<'a>(&'a self, leaves: &mut Vec<&'a T>) {
+		if let NonEmpty(ref node) = *self {
+			if node.is_leaf() {
+				leaves.push(&node.element);
+			}
+			node.left.collect_leaves(leaves);
+			node.right.collect_leaves(leaves);
+		}
+	}
+}
+
+impl<T> TreeNode<T> {
+	fn is_leaf(&self) -> bool {
+		matches!((&self.left, &self.right), (Empty, Empty))
+	}
+}
+
+/// Types with a notion of how far apart two values are, for finding the nearest one.
+pub trait Distance {
+	type Output: PartialOrd;
+	fn distance(&self, other: &Self) -> Self::Output;
+}
+
+macro_rules! integer_distance {
+	($($t:ty => $output:ty),*) => {$(
+		impl Distance for $t {
+			type Output = $output;
+			fn distance(&self, other: &$t) -> $output {
+				self.abs_diff(*other)
+			}
+		}
+	)*}
+}
+
+// Signed types measure distance in their unsigned counterparts, which can hold the distance
+// from the minimum to the maximum.
+integer_distance!(u8 => u8, u16 => u16, u32 => u32, u64 => u64, u128 => u128, usize => usize,
+				  i8 => u8, i16 => u16, i32 => u32, i64 => u64, i128 => u128, isize => usize);
+
+impl<T: Ord + Distance> BinaryTree<T> {
+	/// Return the element nearest to 'target', or 'None' if the tree is empty. When two
+	/// elements are equally near, the smaller wins, so in a tree of 5 and 9, 7 is closest to 5.
+	///
+	/// This follows a single path down from the root, so it takes time proportional to the
+	/// height of the tree rather than its size.
+	pub fn closest(&self, target: &T) -> Option<&T> {
+		let mut best: Option<(&T, T::Output)> = None;
+		let mut tree = self;
+		while let NonEmpty(ref node) = *tree {
+			let distance = node.element.distance(target);
+			let nearer = match best {
+				None => true,
+				Some((element, ref best_distance)) => {
+					distance < *best_distance || (distance == *best_distance && node.element < *element)
+				}
+			};
+			if nearer {
+				best = Some((&node.element, distance));
+			}
+
+			tree = match target.cmp(&node.element) {
+				Ordering::Less => &node.left,
+				Ordering::Greater => &node.right,
+				Ordering::Equal => break,
+			};
+		}
+		best.map(|(element, _)| element)
+	}
+}
+
+impl<T: PartialEq> BinaryTree<T> {
+	/// Return true if the tree is its own mirror image: the left subtree has the same shape
+	/// and elements as the right subtree reflected left to right. An empty tree is symmetric.
+	pub fn is_symmetric(&self) -> bool {
+		let mut pairs = Vec::new();
+		if let NonEmpty(ref node) = *self {
+			pairs.push((&node.left, &node.right));
+		}
+
+		// Each pair holds two subtrees that must be reflections of each other.
+		while let Some(pair) = pairs.pop() {
+			match pair {
+				(Empty, Empty) => {}
+				(NonEmpty(left), NonEmpty(right)) => {
+					if left.element != right.element {
+						return false;
+					}
+					pairs.push((&left.left, &right.right));
+					pairs.push((&left.right, &right.left));
+				}
+				_ => return false,
+			}
+		}
+		true
+	}
+}
+
+impl<T: Clone> BinaryTree<T> {
+	/// Build a tree from its level-order serialization, as used by LeetCode and friends: the
+	/// root first, then the children of each present node in turn, left before right, with
+	/// 'None' where a child is missing. '[5, 3, 8, None, 4]' has 5 at the root, 3 and 8 below
+	/// it, and 4 as the right child of 3.
+	///
+	/// Missing nodes have no children listed, and trailing 'None's may be left off.
+	pub fn from_level_order(values: &[Option<T>]) -> BinaryTree<T> {
+		let mut tree = Empty;
+		let mut values = values.iter();
+		if let Some(Some(root)) = values.next() {
+			tree = BinaryTree::leaf(root.clone());
+		}
+
+		let mut queue = VecDeque::new();
+		if let NonEmpty(ref mut node) = tree {
+			queue.push_back(&mut **node);
+		}
+		'filling: while let Some(node) = queue.pop_front() {
+			let TreeNode { ref mut left, ref mut right, .. } = *node;
+			for child in [left, right] {
+				match values.next() {
+					None => break 'filling,
+					Some(None) => {}
+					Some(Some(value)) => {
+						*child = BinaryTree::leaf(value.clone());
+						if let NonEmpty(ref mut child) = *child {
+							queue.push_back(&mut **child);
+						}
+					}
+				}
+			}
+		}
+		tree
+	}
+
+	/// Serialize the tree in level order, the inverse of 'from_level_order'. Trailing 'None's
+	/// are trimmed, so the result is the shortest such serialization.
+	pub fn to_level_order(&self) -> Vec<Option<T>> {
+		let mut values = Vec::new();
+		let mut queue = VecDeque::new();
+		queue.push_back(self);
+		while let Some(tree) = queue.pop_front() {
+			match *tree {
+				Empty => values.push(None),
+				NonEmpty(ref node) => {
+					values.push(Some(node.element.clone()));
+					queue.push_back(&node.left);
+					queue.push_back(&node.right);
+				}
+			}
+		}
+
+		while let Some(&None) = values.last() {
+			values.pop();
+		}
+		values
+	}
+}
+
+impl<T> BinaryTree<T> {
+	/// A tree holding just 'element'.
+	pub fn leaf(element: T) -> BinaryTree<T> {
+		NonEmpty(Box::new(TreeNode {
+			element,
+			left: Empty,
+			right: Empty,
+		}))
+	}
+
+	/// Remove and return the leftmost element, which is the smallest if the tree is a search
+	/// tree, or return 'None' if the tree is empty. Its right subtree takes its place, so this
+	/// takes time proportional to the height of the tree, and popping until 'None' drains a
+	/// search tree in order.
+	pub fn pop_min(&mut self) -> Option<T> {
+		let mut tree = self;
+		while matches!(*tree, NonEmpty(ref node) if matches!(node.left, NonEmpty(_))) {
+			tree = match *tree {
+				NonEmpty(ref mut node) => &mut node.left,
+				Empty => unreachable!(),
+			};
+		}
+
+		match std::mem::replace(tree, Empty) {
+			Empty => None,
+			NonEmpty(node) => {
+				let TreeNode { element, right, .. } = *node;
+				*tree = right;
+				Some(element)
+			}
+		}
+	}
+}
+
+impl<T> BinaryTree<T> {
+	/// Iterate over the elements in order, smallest first if the tree is a search tree.
+	pub fn iter(&self) -> TreeIter<'_, T> {
+		let mut iter = TreeIter { unvisited: Vec::new() };
+		iter.push_left_edge(self);
+		iter
+	}
+
+	/// Return the number of elements in the tree.
+	pub fn len(&self) -> usize {
+		self.iter().count()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		matches!(*self, Empty)
+	}
+
+	/// Fold the elements in order, like 'Iterator::try_fold', stopping at the first 'Err' that
+	/// 'f' returns and handing it back. No element after the one that failed is visited.
+	pub fn try_fold<B, E, F: FnMut(B, &T) -> Result<B, E>>(&self, init: B, f: F) -> Result<B, E> {
+		self.iter().try_fold(init, f)
+	}
+}
+
+impl<'a, T: 'a> IntoIterator for &'a BinaryTree<T> {
+	type Item = &'a T;
+	type IntoIter = TreeIter<'a, T>;
+	fn into_iter(self) -> Self::IntoIter {
+		self.iter()
+	}
+}
+
+/// An in-order iterator over a BinaryTree.
+pub struct TreeIter<'a, T: 'a> {
+	/// A stack of the nodes we have yet to visit. The top of the stack is the next node to
+	/// visit, and its ancestors we have not yet visited lie below it.
+	unvisited: Vec<&'a TreeNode<T>>,
+}
+
+impl<'a, T: 'a> TreeIter<'a, T> {
+	fn push_left_edge(&mut self, mut tree: &'a BinaryTree<T>) {
+		while let NonEmpty(ref node) = *tree {
+			self.unvisited.push(node);
+			tree = &node.left;
+		}
+	}
+}
+
+impl<'a, T> Iterator for TreeIter<'a, T> {
+	type Item = &'a T;
+	fn next(&mut self) -> Option<&'a T> {
+		let node = self.unvisited.pop()?;
+		self.push_left_edge(&node.right);
+		Some(&node.element)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn tree_of(values: &[i32]) -> BinaryTree<i32> {
+		let mut tree = Empty;
+		for &value in values {
+			tree.add(value);
+		}
+		tree
+	}
+
+	#[test]
+	fn levels_are_top_to_bottom() {
+		let tree = tree_of(&[5, 3, 8, 1, 4, 2]);
+		assert_eq!(tree.levels(), vec![vec![&5], vec![&3, &8], vec![&1, &4], vec![&2]]);
+		assert!(tree_of(&[]).levels().is_empty());
+	}
+
+	#[test]
+	fn side_views() {
+		//       5
+		//     3   8
+		//    1 4
+		//     2
+		// The right subtree stops at 8, so the lower levels are seen through the left subtree.
+		let tree = tree_of(&[5, 3, 8, 1, 4, 2]);
+		assert_eq!(tree.right_side_view(), vec![&5, &8, &4, &2]);
+		assert_eq!(tree.left_side_view(), vec![&5, &3, &1, &2]);
+		assert!(tree_of(&[]).right_side_view().is_empty());
+	}
+
+	#[test]
+	fn width_is_the_fullest_level() {
+		//          5
+		//      3       8
+		//    1   4   7   9
+		//     2
+		let tree = tree_of(&[5, 3, 8, 1, 4, 7, 9, 2]);
+		assert_eq!(tree.width(), 4);
+
+		// Only the ends of the bottom level are present.
+		let sparse = BinaryTree::from_level_order(&[Some(1), Some(2), Some(3), Some(4), None,
+													None, Some(5)]);
+		assert_eq!(sparse.width(), 2);
+		assert_eq!(tree_of(&[5]).width(), 1);
+		assert_eq!(tree_of(&[]).width(), 0);
+	}
+
+	#[test]
+	fn parses_level_order() {
+		let tree = BinaryTree::from_level_order(&[Some(5), Some(3), Some(8), None, Some(4)]);
+		assert_eq!(tree.levels(), vec![vec![&5], vec![&3, &8], vec![&4]]);
+		assert_eq!(tree.right_side_view(), vec![&5, &8, &4]);
+		assert_eq!(tree.to_level_order(), vec![Some(5), Some(3), Some(8), None, Some(4)]);
+
+		assert!(BinaryTree::<i32>::from_level_order(&[]).levels().is_empty());
+		assert!(BinaryTree::<i32>::from_level_order(&[None]).levels().is_empty());
+	}
+
+	#[test]
+	fn level_order_round_trip() {
+		let tree = tree_of(&[5, 3, 8, 1, 4, 2, 9]);
+		let values = tree.to_level_order();
+		assert_eq!(values, vec![Some(5), Some(3), Some(8), Some(1), Some(4), None, Some(9),
+								None, Some(2)]);
+		assert_eq!(BinaryTree::from_level_order(&values).to_level_order(), values);
+		assert!(tree_of(&[]).to_level_order().is_empty());
+	}
+
+	/// A key with a payload that plays no part in the ordering, as in a map.
+	#[derive(Debug)]
+	struct Entry {
+		key: i32,
+		hits: u32,
+	}
+
+	impl PartialEq for Entry {
+		fn eq(&self, other: &Entry) -> bool { self.key == other.key }
+	}
+	impl Eq for Entry {}
+	impl PartialOrd for Entry {
+		fn partial_cmp(&self, other: &Entry) -> Option<Ordering> { Some(self.cmp(other)) }
+	}
+	impl Ord for Entry {
+		fn cmp(&self, other: &Entry) -> Ordering { self.key.cmp(&other.key) }
+	}
+
+	#[test]
+	fn insert_ref_aliases_the_stored_element() {
+		let mut tree = tree_of(&[5, 3, 8]);
+		let inserted = tree.insert_ref(4) as *const i32;
+		let existing = tree.insert_ref(8) as *const i32;
+
+		let levels = tree.levels();
+		assert_eq!(levels, vec![vec![&5], vec![&3, &8], vec![&4]]);
+		assert!(std::ptr::eq(inserted, levels[2][0]));
+		assert!(std::ptr::eq(existing, levels[1][1]));
+
+		let mut counts = BinaryTree::Empty;
+		for &key in &[2, 1, 2, 3, 2] {
+			counts.insert_ref(Entry { key, hits: 0 }).hits += 1;
+		}
+		let hits: Vec<(i32, u32)> = counts.levels().into_iter()
+			.flat_map(|level| level.into_iter().map(|entry| (entry.key, entry.hits)))
+			.collect();
+		assert_eq!(hits, vec![(2, 3), (1, 1), (3, 1)]);
+	}
+
+	#[test]
+	fn paths_to_every_leaf() {
+		//     5
+		//   3   8
+		//  1 4
+		let tree = tree_of(&[5, 3, 8, 1, 4]);
+		assert_eq!(tree.root_to_leaf_paths(), vec![vec![&5, &3, &1], vec![&5, &3, &4], vec![&5, &8]]);
+		assert_eq!(tree_of(&[7]).root_to_leaf_paths(), vec![vec![&7]]);
+		assert!(tree_of(&[]).root_to_leaf_paths().is_empty());
+	}
+
+	#[test]
+	fn iterates_in_order() {
+		let tree = tree_of(&[5, 3, 8, 1, 4, 9, 7]);
+		assert_eq!(tree.iter().cloned().collect::<Vec<_>>(), vec![1, 3, 4, 5, 7, 8, 9]);
+		assert_eq!((&tree).into_iter().count(), 7);
+		assert_eq!(tree_of(&[]).iter().next(), None);
+	}
+
+	#[test]
+	fn try_fold_stops_at_the_first_error() {
+		let tree = tree_of(&[5, 3, 8, 1, 4, 9, 7]);
+		assert_eq!(tree.try_fold(0, |sum, &x| Ok::<i32, ()>(sum + x)), Ok(37));
+
+		// Sum the elements below 6, failing at 7 with the sum so far.
+		let mut visited = Vec::new();
+		let result = tree.try_fold(0, |sum, &x| {
+			visited.push(x);
+			if x < 6 { Ok(sum + x) } else { Err((x, sum)) }
+		});
+		assert_eq!(result, Err((7, 13)));
+		assert_eq!(visited, vec![1, 3, 4, 5, 7]);
+
+		assert_eq!(tree_of(&[]).try_fold(10, |_, _| Err(())), Ok(10));
+	}
+
+	#[test]
+	fn finds_subtrees() {
+		//        5
+		//     3     8
+		//    1 4   7 9
+		//     2   6
+		let tree = tree_of(&[5, 3, 8, 1, 4, 7, 9, 2, 6]);
+		let eight = tree.subtree(&8).unwrap();
+		assert_eq!(eight.len(), 4);
+		assert_eq!(eight.min(), Some(&6));
+		assert_eq!(eight.max(), Some(&9));
+		assert_eq!(eight.iter().cloned().collect::<Vec<_>>(), vec![6, 7, 8, 9]);
+
+		let one = tree.subtree(&1).unwrap();
+		assert_eq!((one.len(), one.min(), one.max()), (2, Some(&1), Some(&2)));
+		assert_eq!(tree.subtree(&5).unwrap().len(), tree.len());
+		assert!(tree.subtree(&10).is_none());
+
+		let empty = tree_of(&[]);
+		assert!(empty.is_empty() && !tree.is_empty());
+		assert_eq!((empty.len(), empty.min(), empty.max()), (0, None, None));
+	}
+
+	#[test]
+	fn extends_in_one_balanced_rebuild() {
+		// A balanced tree of the even numbers below 2000.
+		let mut tree = BinaryTree::Empty;
+		tree.extend_balanced((0..1000).map(|i| i * 2));
+		assert_eq!(tree.levels().len(), 10);
+
+		// Add the odd numbers in a scrambled order, with a few repeats and old values mixed in.
+		let mut batch: Vec<i32> = (0..1000).map(|i| (i * 7919) % 1000 * 2 + 1).collect();
+		batch.extend(&[1, 3, 0, 1998]);
+		tree.extend_balanced(batch);
+
+		assert_eq!(tree.iter().cloned().collect::<Vec<_>>(), (0..2000).collect::<Vec<_>>());
+		assert_eq!(tree.len(), 2000);
+		// 2000 elements need 11 levels at the least.
+		assert_eq!(tree.levels().len(), 11);
+		assert_eq!(tree.count_out_of_order(), 0);
+
+		let mut empty = tree_of(&[]);
+		empty.extend_balanced(Vec::new());
+		assert!(empty.is_empty());
+	}
+
+	#[test]
+	fn extend_keeps_the_elements_already_there() {
+		let mut tree = BinaryTree::Empty;
+		tree.add(Entry { key: 1, hits: 10 });
+		tree.extend_balanced(vec![Entry { key: 1, hits: 20 }, Entry { key: 0, hits: 30 },
+								  Entry { key: 0, hits: 40 }]);
+		let entries: Vec<(i32, u32)> = tree.iter().map(|entry| (entry.key, entry.hits)).collect();
+		assert_eq!(entries, vec![(0, 30), (1, 10)]);
+	}
+
+	#[test]
+	fn counts_out_of_order_pairs() {
+		assert_eq!(tree_of(&[5, 3, 8, 1, 4, 9, 7]).count_out_of_order(), 0);
+		assert_eq!(tree_of(&[]).count_out_of_order(), 0);
+
+		// The search tree [5, 3, 8, 1, 4] with 3 and 8 swapped reads 1, 8, 4, 5, 3 in order.
+		let swapped = BinaryTree::from_level_order(&[Some(5), Some(8), Some(3), Some(1), Some(4)]);
+		assert_eq!(swapped.count_out_of_order(), 2);
+
+		// Duplicates count too, since a search tree holds each value once.
+		let duplicated = BinaryTree::from_level_order(&[Some(5), Some(5)]);
+		assert_eq!(duplicated.count_out_of_order(), 1);
+	}
+
+	#[test]
+	fn recovers_from_two_swapped_elements() {
+		let tree = tree_of(&[5, 3, 8, 1, 4, 9, 7, 2, 6]);
+		let shape = tree.to_level_order();
+		let filled: Vec<usize> = (0..shape.len()).filter(|&i| shape[i].is_some()).collect();
+
+		// Every pair of nodes, whether next to each other in order, like 4 and 5, or not.
+		for (n, &i) in filled.iter().enumerate() {
+			for &j in &filled[n + 1..] {
+				let mut corrupted = shape.clone();
+				corrupted.swap(i, j);
+				let mut tree = BinaryTree::from_level_order(&corrupted);
+				assert!(tree.count_out_of_order() > 0);
+
+				tree.recover_bst();
+				assert_eq!(tree.to_level_order(), shape, "swapped {:?} and {:?}", shape[i], shape[j]);
+				assert_eq!(tree.count_out_of_order(), 0);
+			}
+		}
+
+		let mut sorted = tree_of(&[2, 1, 3]);
+		sorted.recover_bst();
+		assert_eq!(sorted.to_level_order(), vec![Some(2), Some(1), Some(3)]);
+		let mut empty = tree_of(&[]);
+		empty.recover_bst();
+		assert!(empty.is_empty());
+	}
+
+	#[test]
+	fn finds_ancestors_along_the_search_path() {
+		//       5
+		//     3   8
+		//    1 4
+		//     2
+		let tree = tree_of(&[5, 3, 8, 1, 4, 2]);
+		assert_eq!(tree.path_to(&2), Some(vec![&5, &3, &1, &2]));
+		assert_eq!(tree.path_to(&6), None);
+
+		assert_eq!(tree.kth_ancestor(&2, 0), Some(&2));
+		assert_eq!(tree.kth_ancestor(&2, 1), Some(&1));
+		assert_eq!(tree.kth_ancestor(&2, 3), Some(&5));
+		assert_eq!(tree.kth_ancestor(&2, 4), None);
+		assert_eq!(tree.kth_ancestor(&5, 1), None);
+		assert_eq!(tree.kth_ancestor(&6, 0), None);
+	}
+
+	#[test]
+	fn checks_for_mirror_symmetry() {
+		//      1
+		//    2   2
+		//   3 4 4 3
+		let symmetric = BinaryTree::from_level_order(&[Some(1), Some(2), Some(2), Some(3), Some(4),
+													   Some(4), Some(3)]);
+		assert!(symmetric.is_symmetric());
+		assert!(tree_of(&[]).is_symmetric());
+		assert!(tree_of(&[7]).is_symmetric());
+
+		let by_value = BinaryTree::from_level_order(&[Some(1), Some(2), Some(2), Some(3), Some(4),
+													  Some(3), Some(4)]);
+		assert!(!by_value.is_symmetric());
+
+		// The same values, but both lower 3s hang to the right.
+		let by_shape = BinaryTree::from_level_order(&[Some(1), Some(2), Some(2), None, Some(3),
+													  None, Some(3)]);
+		assert!(!by_shape.is_symmetric());
+	}
+
+	#[test]
+	fn finds_the_closest_element() {
+		let tree = tree_of(&[9, 5, 13, 1]);
+		assert_eq!(tree.closest(&7), Some(&5));
+		assert_eq!(tree.closest(&8), Some(&9));
+		assert_eq!(tree.closest(&13), Some(&13));
+		assert_eq!(tree.closest(&-50), Some(&1));
+		assert_eq!(tree.closest(&i32::MAX), Some(&13));
+		assert_eq!(tree_of(&[]).closest(&7), None);
+
+		// Distances that don't fit in a signed type are computed without overflowing.
+		let mut extremes = BinaryTree::Empty;
+		extremes.add(i8::MIN);
+		extremes.add(i8::MAX);
+		assert_eq!(extremes.closest(&0), Some(&i8::MAX));
+		assert_eq!(extremes.closest(&-1), Some(&i8::MIN));
+	}
+
+	#[test]
+	fn pops_in_sorted_order() {
+		let mut tree = tree_of(&[5, 3, 8, 1, 4, 9, 7, 2]);
+		let mut popped = Vec::new();
+		while let Some(element) = tree.pop_min() {
+			popped.push(element);
+		}
+		assert_eq!(popped, vec![1, 2, 3, 4, 5, 7, 8, 9]);
+		assert_eq!(tree.pop_min(), None);
+
+		// The root is the minimum, so its right subtree becomes the whole tree.
+		let mut tree = tree_of(&[1, 5, 3, 8]);
+		assert_eq!(tree.pop_min(), Some(1));
+		assert_eq!(tree.levels(), vec![vec![&5], vec![&3, &8]]);
+	}
+
+	/// Reorder 'sorted' so that each subrange's middle element comes before the rest of it,
+	/// which is the order that builds a balanced tree.
+	fn middle_first(sorted: &[i32], order: &mut Vec<i32>) {
+		if let Some(&middle) = sorted.get(sorted.len() / 2) {
+			order.push(middle);
+			middle_first(&sorted[..sorted.len() / 2], order);
+			middle_first(&sorted[sorted.len() / 2 + 1..], order);
+		}
+	}
+
+	#[test]
+	fn counts_comparisons() {
+		let n = 1023;
+		let sorted: Vec<i32> = (0..n).collect();
+		let (chain, comparisons) = BinaryTree::from_iter_counting(sorted.clone());
+		assert_eq!(comparisons, (n * (n - 1) / 2) as usize);
+		assert_eq!(chain.levels().len(), n as usize);
+
+		let mut order = Vec::new();
+		middle_first(&sorted, &mut order);
+		let (balanced, comparisons) = BinaryTree::from_iter_counting(order);
+		assert_eq!(balanced.levels().len(), 10);
+		assert!(comparisons < 10 * n as usize);
+		assert_eq!(balanced.iter().cloned().collect::<Vec<_>>(), sorted);
+
+		assert_eq!(BinaryTree::from_iter_counting(vec![1, 1, 1]).1, 2);
+		assert_eq!(BinaryTree::<i32>::from_iter_counting(vec![]).1, 0);
+	}
+
+	#[test]
+	fn boundary_goes_anti_clockwise() {
+		//         5
+		//      3     8
+		//    1   4  7 9
+		//     2
+		// 2, at the bottom of the left boundary, and 9, at the bottom of the right, are leaves
+		// and are listed only once.
+		let tree = tree_of(&[5, 3, 8, 1, 4, 2, 9, 7]);
+		assert_eq!(tree.boundary(), vec![&5, &3, &1, &2, &4, &7, &9, &8]);
+
+		// With no right subtree, the leftmost leaf closes the outline.
+		assert_eq!(tree_of(&[5, 3, 1]).boundary(), vec![&5, &3, &1]);
+		assert_eq!(tree_of(&[5, 3, 8, 1]).boundary(), vec![&5, &3, &1, &8]);
+		assert_eq!(tree_of(&[5]).boundary(), vec![&5]);
+		assert!(tree_of(&[]).boundary().is_empty());
+	}
+}
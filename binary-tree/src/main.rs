@@ -1,19 +1,14 @@
-enum BinaryTree<T> {
-	Empty,
-	NonEmpty(Box<TreeNode<T>>)
-}
-
-struct TreeNode<T> {
-	element: T,
-	left: BinaryTree<T>,
-	right: BinaryTree<T>,
-}
-
-use self::BinaryTree::*;
-use std::fmt::Display;
-
+extern crate binary_tree;
 
+use binary_tree::BinaryTree;
 
 fn main() {
-
+	let mut tree = BinaryTree::Empty;
+	for planet in &["Mercury", "Venus", "Earth", "Mars", "Jupiter", "Saturn", "Uranus", "Neptune"] {
+		tree.add(*planet);
+	}
+
+	println!("in order: {:?}", tree.iter().collect::<Vec<_>>());
+	println!("seen from the left: {:?}", tree.left_side_view());
+	println!("seen from the right: {:?}", tree.right_side_view());
 }
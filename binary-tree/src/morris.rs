@@ -0,0 +1,191 @@
+//! A tree that can be walked in order by Morris traversal, which needs neither a stack nor
+//! permanent threads: it borrows the empty right links of the tree as temporary threads back
+//! up to where it has to return, and clears each one on its way past.
+//!
+//! A 'BinaryTree' can't be walked this way in place, because its links are 'Box'es, and a
+//! thread would give the node it points to a second owner. So 'MorrisTree' keeps its nodes
+//! behind raw pointers instead, and is made from a 'BinaryTree' and turned back into one. The
+//! price is that conversion, and that the traversal needs '&mut self': it rewires the tree as
+//! it goes, so nothing else may look at the tree until it's done.
+
+use std::marker::PhantomData;
+use std::ptr;
+
+use super::{BinaryTree, TreeNode};
+
+struct MorrisNode<T> {
+	element: T,
+	/// The left child, or null.
+	left: *mut MorrisNode<T>,
+	/// The right child, or null. During a traversal, it may instead be a thread to this
+	/// node's in-order successor, which doesn't own what it points to.
+	right: *mut MorrisNode<T>,
+}
+
+/// A tree with the same shape and elements as the 'BinaryTree' it was made from, whose
+/// in-order traversal uses no memory beyond a couple of pointers.
+pub struct MorrisTree<T> {
+	root: *mut MorrisNode<T>,
+}
+
+impl<T> MorrisTree<T> {
+	/// Take over the elements of 'tree', keeping its shape.
+	pub fn from_tree(tree: BinaryTree<T>) -> MorrisTree<T> {
+		MorrisTree { root: into_raw(tree) }
+	}
+
+	/// Give the elements back as a 'BinaryTree' of the same shape.
+	pub fn into_tree(mut self) -> BinaryTree<T> {
+		let root = self.root;
+		self.root = ptr::null_mut();
+		unsafe { from_raw(root) }
+	}
+
+	/// Call 'f' on each element in order. Every thread the traversal makes is removed again,
+	/// even if 'f' panics, so the tree afterwards is just as it was before.
+	pub fn for_each_morris<F: FnMut(&T)>(&mut self, mut f: F) {
+		let mut walk = Walk { current: self.root, tree: PhantomData };
+		while let Some(node) = walk.step() {
+			f(unsafe { &(*node).element });
+		}
+	}
+}
+
+/// A Morris traversal in progress. Dropping it before the end finishes the walk without
+/// visiting anything, to take down the threads still in place.
+struct Walk<'a, T: 'a> {
+	/// The root of the part of the tree not yet visited, or null when there's none left.
+	current: *mut MorrisNode<T>,
+	tree: PhantomData<&'a mut MorrisTree<T>>,
+}
+
+impl<'a, T> Walk<'a, T> {
+	/// Return the next node in order, or 'None' at the end.
+	fn step(&mut self) -> Option<*mut MorrisNode<T>> {
+		unsafe {
+			while !self.current.is_null() {
+				let current = self.current;
+				if (*current).left.is_null() {
+					self.current = (*current).right;
+					return Some(current);
+				}
+
+				// Find the rightmost node of the left subtree, which comes just before us.
+				let mut predecessor = (*current).left;
+				while !(*predecessor).right.is_null() && (*predecessor).right != current {
+					predecessor = (*predecessor).right;
+				}
+
+				if (*predecessor).right.is_null() {
+					// First time here: leave a thread to come back by, and go left.
+					(*predecessor).right = current;
+					self.current = (*current).left;
+				} else {
+					// Back along the thread, so the left subtree is done.
+					(*predecessor).right = ptr::null_mut();
+					self.current = (*current).right;
+					return Some(current);
+				}
+			}
+			None
+		}
+	}
+}
+
+impl<'a, T> Drop for Walk<'a, T> {
+	fn drop(&mut self) {
+		while self.step().is_some() {}
+	}
+}
+
+/// Move the nodes of 'tree' onto the heap as 'MorrisNode's, returning the root.
+fn into_raw<T>(tree: BinaryTree<T>) -> *mut MorrisNode<T> {
+	match tree {
+		BinaryTree::Empty => ptr::null_mut(),
+		BinaryTree::NonEmpty(node) => {
+			let TreeNode { element, left, right } = *node;
+			Box::into_raw(Box::new(MorrisNode { element, left: into_raw(left), right: into_raw(right) }))
+		}
+	}
+}
+
+/// The reverse of 'into_raw'. 'node' must be null or have come from 'into_raw', with no threads
+/// left in its subtree, and must not be used again.
+unsafe fn from_raw<T>(node: *mut MorrisNode<T>) -> BinaryTree<T> {
+	if node.is_null() {
+		return BinaryTree::Empty;
+	}
+	let node = Box::from_raw(node);
+	let MorrisNode { element, left, right } = *node;
+	BinaryTree::NonEmpty(Box::new(TreeNode { element, left: from_raw(left), right: from_raw(right) }))
+}
+
+impl<T> Drop for MorrisTree<T> {
+	fn drop(&mut self) {
+		let mut unvisited = vec![self.root];
+		while let Some(node) = unvisited.pop() {
+			if node.is_null() {
+				continue;
+			}
+			let node = unsafe { Box::from_raw(node) };
+			unvisited.push(node.left);
+			unvisited.push(node.right);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::panic::{self, AssertUnwindSafe};
+
+	fn tree_of(values: &[i32]) -> BinaryTree<i32> {
+		let mut tree = BinaryTree::Empty;
+		for &value in values {
+			tree.add(value);
+		}
+		tree
+	}
+
+	fn morris_order(tree: &mut MorrisTree<i32>) -> Vec<i32> {
+		let mut order = Vec::new();
+		tree.for_each_morris(|&x| order.push(x));
+		order
+	}
+
+	#[test]
+	fn matches_the_stack_based_order() {
+		let values = [5, 3, 8, 1, 4, 9, 7, 2, 6, 10];
+		let tree = tree_of(&values);
+		let expected: Vec<i32> = tree.iter().cloned().collect();
+		let shape = tree.to_level_order();
+
+		let mut morris = MorrisTree::from_tree(tree);
+		assert_eq!(morris_order(&mut morris), expected);
+		assert_eq!(morris_order(&mut morris), expected);
+		assert_eq!(morris.into_tree().to_level_order(), shape);
+
+		assert!(morris_order(&mut MorrisTree::from_tree(tree_of(&[]))).is_empty());
+	}
+
+	#[test]
+	fn restores_the_tree_after_a_panic() {
+		let tree = tree_of(&[5, 3, 8, 1, 4, 9, 7, 2]);
+		let shape = tree.to_level_order();
+		let mut morris = MorrisTree::from_tree(tree);
+
+		// Stop partway, while the threads through 5's left subtree are still up.
+		let mut visited = Vec::new();
+		let result = panic::catch_unwind(AssertUnwindSafe(|| {
+			morris.for_each_morris(|&x| {
+				assert!(x != 4, "stopping at 4");
+				visited.push(x);
+			})
+		}));
+		assert!(result.is_err());
+		assert_eq!(visited, vec![1, 2, 3]);
+
+		assert_eq!(morris_order(&mut morris), vec![1, 2, 3, 4, 5, 7, 8, 9]);
+		assert_eq!(morris.into_tree().to_level_order(), shape);
+	}
+}
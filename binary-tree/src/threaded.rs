@@ -0,0 +1,193 @@
+//! A threaded binary tree, in which every node without a right child points to its in-order
+//! successor instead, so the tree can be walked in order without a stack.
+
+#[cfg(debug_assertions)]
+use std::collections::HashSet;
+use std::marker::PhantomData;
+use std::ptr;
+
+use super::{BinaryTree, TreeNode};
+
+struct ThreadedNode<T> {
+	element: T,
+	/// The left child, or null.
+	left: *mut ThreadedNode<T>,
+	/// The right child, or if 'threaded' is set, the in-order successor. Either may be null.
+	right: *mut ThreadedNode<T>,
+	/// Whether 'right' is a thread rather than a child. Threads don't own what they point to.
+	threaded: bool,
+}
+
+/// A tree built from a 'BinaryTree', with the same shape and elements, whose in-order
+/// iterator needs no memory of its own.
+pub struct ThreadedTree<T> {
+	root: *mut ThreadedNode<T>,
+}
+
+impl<T> ThreadedTree<T> {
+	/// Take over the nodes of 'tree' and thread them.
+	pub fn from_tree(tree: BinaryTree<T>) -> ThreadedTree<T> {
+		let threaded = ThreadedTree { root: into_raw(tree) };
+
+		// Visit the nodes in order, pointing each childless right link at the next node.
+		let mut unvisited = Vec::new();
+		let mut previous: *mut ThreadedNode<T> = ptr::null_mut();
+		let mut node = threaded.root;
+		unsafe {
+			while !node.is_null() || !unvisited.is_empty() {
+				while !node.is_null() {
+					unvisited.push(node);
+					node = (*node).left;
+				}
+				node = unvisited.pop().unwrap();
+				if !previous.is_null() && (*previous).right.is_null() {
+					(*previous).right = node;
+					(*previous).threaded = true;
+				}
+				previous = node;
+				node = (*node).right;
+			}
+			if !previous.is_null() {
+				(*previous).threaded = true;
+			}
+		}
+
+		threaded.assert_acyclic();
+		threaded
+	}
+
+	/// Iterate over the elements in order, following the threads.
+	pub fn iter(&self) -> ThreadedIter<'_, T> {
+		ThreadedIter { next: leftmost(self.root), tree: PhantomData }
+	}
+
+	/// In debug builds, check that following child links, as opposed to threads, never reaches
+	/// the same node twice, and panic if it does. Safe code can't break this rule, but the
+	/// threading is done with raw pointers, and a mistake there could tie the tree in a loop
+	/// or hand one node to two parents, which would make dropping it free a node twice.
+	///
+	/// In release builds this does nothing.
+	pub fn assert_acyclic(&self) {
+		#[cfg(debug_assertions)]
+		{
+			let mut visited = HashSet::new();
+			let mut unvisited = vec![self.root];
+			while let Some(node) = unvisited.pop() {
+				if node.is_null() {
+					continue;
+				}
+				assert!(visited.insert(node), "tree node reached twice through child links");
+				unsafe {
+					unvisited.push((*node).left);
+					if !(*node).threaded {
+						unvisited.push((*node).right);
+					}
+				}
+			}
+		}
+	}
+}
+
+/// Move the nodes of 'tree' onto the heap as unthreaded 'ThreadedNode's, returning the root.
+fn into_raw<T>(tree: BinaryTree<T>) -> *mut ThreadedNode<T> {
+	match tree {
+		BinaryTree::Empty => ptr::null_mut(),
+		BinaryTree::NonEmpty(node) => {
+			let TreeNode { element, left, right } = *node;
+			Box::into_raw(Box::new(ThreadedNode {
+				element,
+				left: into_raw(left),
+				right: into_raw(right),
+				threaded: false,
+			}))
+		}
+	}
+}
+
+/// Follow left links from 'node' as far as they go.
+fn leftmost<T>(mut node: *mut ThreadedNode<T>) -> *mut ThreadedNode<T> {
+	unsafe {
+		while !node.is_null() && !(*node).left.is_null() {
+			node = (*node).left;
+		}
+	}
+	node
+}
+
+impl<T> Drop for ThreadedTree<T> {
+	fn drop(&mut self) {
+		let mut unvisited = vec![self.root];
+		while let Some(node) = unvisited.pop() {
+			if node.is_null() {
+				continue;
+			}
+			let node = unsafe { Box::from_raw(node) };
+			unvisited.push(node.left);
+			if !node.threaded {
+				unvisited.push(node.right);
+			}
+		}
+	}
+}
+
+/// An in-order iterator over a ThreadedTree.
+pub struct ThreadedIter<'a, T: 'a> {
+	next: *mut ThreadedNode<T>,
+	tree: PhantomData<&'a ThreadedTree<T>>,
+}
+
+impl<'a, T> Iterator for ThreadedIter<'a, T> {
+	type Item = &'a T;
+	fn next(&mut self) -> Option<&'a T> {
+		if self.next.is_null() {
+			return None;
+		}
+		unsafe {
+			let node = &*self.next;
+			self.next = if node.threaded { node.right } else { leftmost(node.right) };
+			Some(&node.element)
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::panic::{self, AssertUnwindSafe};
+
+	fn threaded_of(values: &[i32]) -> ThreadedTree<i32> {
+		let mut tree = BinaryTree::Empty;
+		for &value in values {
+			tree.add(value);
+		}
+		ThreadedTree::from_tree(tree)
+	}
+
+	#[test]
+	fn iterates_along_threads() {
+		let tree = threaded_of(&[5, 3, 8, 1, 4, 9, 7, 2]);
+		assert_eq!(tree.iter().cloned().collect::<Vec<_>>(), vec![1, 2, 3, 4, 5, 7, 8, 9]);
+		tree.assert_acyclic();
+		assert_eq!(threaded_of(&[]).iter().next(), None);
+	}
+
+	#[test]
+	fn catches_mislinked_nodes() {
+		// Turn the thread from 1 to its successor, its parent 3, into a child link, so that 3
+		// becomes its own grandchild.
+		let tree = threaded_of(&[5, 3, 8, 1]);
+		let one = leftmost(tree.root);
+		unsafe {
+			assert_eq!((*one).element, 1);
+			(*one).threaded = false;
+		}
+
+		let result = panic::catch_unwind(AssertUnwindSafe(|| tree.assert_acyclic()));
+		assert_eq!(result.is_err(), cfg!(debug_assertions));
+
+		// Put the thread back before the tree is dropped.
+		unsafe {
+			(*one).threaded = true;
+		}
+	}
+}
@@ -1,6 +1,6 @@
 extern crate fern_sim;
 
-use fern_sim::{Fern, run_simulation};
+use fern_sim::{Fern, record_simulation, sparkline};
 
 fn main() {
 	let mut fern = Fern {
@@ -8,6 +8,9 @@ fn main() {
 		growth_rate: 0.001
 	};
 
-	run_simulation(&mut fern, 1000);
+	let history = record_simulation(&mut fern, 1000);
 	println!("final fern size: {}", fern.size);
+
+	let every_fifty_days: Vec<f64> = history.iter().step_by(50).cloned().collect();
+	println!("growth: {}", sparkline(&every_fifty_days));
 }
@@ -0,0 +1,239 @@
+//! Ferns sharing a pool of soil nitrogen, which their growth uses up faster than it comes back.
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde_json;
+
+use super::Fern;
+
+/// The version of the file format written by 'Ecosystem::save'. Bump it whenever the format
+/// changes, so that older files are refused rather than misread.
+const CHECKPOINT_VERSION: u32 = 1;
+
+/// A group of ferns drawing on one nitrogen pool. Each unit of size a fern gains takes
+/// 'nitrogen_per_growth' units of nitrogen from the pool, and the pool recovers by
+/// 'replenishment' units a day, up to 'capacity'.
+#[derive(Serialize, Deserialize)]
+pub struct Ecosystem {
+	pub ferns: Vec<Fern>,
+	/// The nitrogen available for the next day's growth.
+	pub nitrogen: f64,
+	pub capacity: f64,
+	pub replenishment: f64,
+	pub nitrogen_per_growth: f64,
+}
+
+impl Ecosystem {
+	/// Simulate one day and return how much nitrogen was used.
+	///
+	/// If the pool holds enough for every fern to grow as 'Fern::grow' would, they all do.
+	/// Otherwise the pool is shared out in proportion to what each fern would have used, every
+	/// fern growing by the same fraction of its usual amount, and the pool is left empty; an
+	/// empty pool means no growth at all that day. The pool recovers at the end of the day.
+	///
+	/// A fern with a negative growth rate shrinks as 'Fern::grow' would, however much nitrogen
+	/// there is. It takes none from the pool, and gives none back.
+	pub fn step(&mut self) -> f64 {
+		let growth: Vec<f64> = self.ferns.iter().map(|fern| fern.size * fern.growth_rate).collect();
+		let demand = growth.iter().map(|growth| growth.max(0.0)).sum::<f64>() * self.nitrogen_per_growth;
+		let fraction = if demand > self.nitrogen { self.nitrogen / demand } else { 1.0 };
+
+		for (fern, growth) in self.ferns.iter_mut().zip(growth) {
+			fern.size += if growth > 0.0 { growth * fraction } else { growth };
+		}
+		let used = (demand * fraction).min(self.nitrogen);
+		self.nitrogen = (self.nitrogen - used + self.replenishment).min(self.capacity);
+		used
+	}
+
+	/// The combined size of all the ferns.
+	pub fn total_size(&self) -> f64 {
+		self.ferns.iter().map(|fern| fern.size).sum()
+	}
+
+	/// Write the whole state of the ecosystem to the file at 'path', so that 'load' can pick the
+	/// simulation up exactly where it was.
+	pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), CheckpointError> {
+		let checkpoint = Checkpoint { version: CHECKPOINT_VERSION, ecosystem: self };
+		let json = serde_json::to_string(&checkpoint).map_err(CheckpointError::Format)?;
+		fs::write(path, json).map_err(CheckpointError::Io)
+	}
+
+	/// Read an ecosystem from a file written by 'save'. Files written in any other version of
+	/// the format are refused.
+	pub fn load<P: AsRef<Path>>(path: P) -> Result<Ecosystem, CheckpointError> {
+		let json = fs::read_to_string(path).map_err(CheckpointError::Io)?;
+
+		// Look at the version before anything else, since the rest may not be laid out as
+		// this version expects.
+		let version: Version = serde_json::from_str(&json).map_err(CheckpointError::Format)?;
+		if version.version != CHECKPOINT_VERSION {
+			return Err(CheckpointError::Version(version.version));
+		}
+		let checkpoint: Checkpoint<Ecosystem> = serde_json::from_str(&json)
+			.map_err(CheckpointError::Format)?;
+		Ok(checkpoint.ecosystem)
+	}
+}
+
+/// The layout of a saved ecosystem: 'E' is '&Ecosystem' when saving and 'Ecosystem' when
+/// loading.
+#[derive(Serialize, Deserialize)]
+struct Checkpoint<E> {
+	version: u32,
+	ecosystem: E,
+}
+
+/// Just the version of a saved ecosystem, whatever else the file holds.
+#[derive(Deserialize)]
+struct Version {
+	version: u32,
+}
+
+/// The error returned when an ecosystem can't be saved or loaded.
+#[derive(Debug)]
+pub enum CheckpointError {
+	/// The file couldn't be read or written.
+	Io(io::Error),
+	/// The file isn't a saved ecosystem.
+	Format(serde_json::Error),
+	/// The file was saved in this version of the format, which this build can't read.
+	Version(u32),
+}
+
+impl fmt::Display for CheckpointError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			CheckpointError::Io(ref error) => write!(f, "error accessing checkpoint: {}", error),
+			CheckpointError::Format(ref error) => write!(f, "malformed checkpoint: {}", error),
+			CheckpointError::Version(version) => {
+				write!(f, "checkpoint has version {}, but only version {} is supported",
+					   version, CHECKPOINT_VERSION)
+			}
+		}
+	}
+}
+
+impl Error for CheckpointError {}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn two_ferns(nitrogen: f64) -> Ecosystem {
+		Ecosystem {
+			ferns: vec![Fern { size: 1.0, growth_rate: 0.5 }, Fern { size: 3.0, growth_rate: 0.1 }],
+			nitrogen,
+			capacity: 10.0,
+			replenishment: 0.2,
+			nitrogen_per_growth: 2.0,
+		}
+	}
+
+	#[test]
+	fn plenty_of_nitrogen_allows_full_growth() {
+		// The ferns would grow by 0.5 and 0.3, using 1.6 of the 10 units.
+		let mut ecosystem = two_ferns(10.0);
+		assert!((ecosystem.step() - 1.6).abs() < 1e-12);
+		assert!((ecosystem.ferns[0].size - 1.5).abs() < 1e-12);
+		assert!((ecosystem.ferns[1].size - 3.3).abs() < 1e-12);
+		assert!((ecosystem.nitrogen - 8.6).abs() < 1e-12);
+	}
+
+	#[test]
+	fn growth_stops_and_resumes_with_the_pool() {
+		let mut ecosystem = two_ferns(5.0);
+		let mut available = ecosystem.nitrogen;
+		let mut consumed = 0.0;
+		let mut ran_dry = false;
+		for day in 0..40 {
+			let before = ecosystem.total_size();
+			let used = ecosystem.step();
+			assert!(used >= 0.0 && ecosystem.nitrogen >= 0.0);
+			consumed += used;
+			available += ecosystem.replenishment;
+			assert!(consumed <= available + 1e-9, "day {}: used {} of {}", day, consumed, available);
+
+			// A day that uses up the pool leaves only the day's replenishment.
+			ran_dry |= ecosystem.nitrogen == ecosystem.replenishment;
+			assert!(ecosystem.total_size() >= before);
+		}
+		assert!(ran_dry, "the pool never ran dry");
+
+		// Once the pool is dry, the ferns grow only as fast as nitrogen comes back.
+		let mut dry = two_ferns(0.0);
+		let before = dry.total_size();
+		assert_eq!(dry.step(), 0.0);
+		assert_eq!(dry.total_size(), before);
+		assert_eq!(dry.nitrogen, 0.2);
+
+		let used = dry.step();
+		assert!((used - 0.2).abs() < 1e-12);
+		assert!((dry.total_size() - before - 0.1).abs() < 1e-12);
+	}
+
+	#[test]
+	fn shrinking_ferns_neither_take_nor_give_nitrogen() {
+		// The second fern would shrink by 0.3; only the first's 0.5 of growth draws on the pool.
+		let mut ecosystem = two_ferns(10.0);
+		ecosystem.ferns[1].growth_rate = -0.1;
+		assert!((ecosystem.step() - 1.0).abs() < 1e-12);
+		assert!((ecosystem.ferns[1].size - 2.7).abs() < 1e-12);
+		assert!((ecosystem.nitrogen - 9.2).abs() < 1e-12);
+
+		// Even with the pool dry, they shrink all the same, and the pool gains only its
+		// replenishment.
+		let mut dry = two_ferns(0.0);
+		dry.ferns[1].growth_rate = -0.1;
+		assert_eq!(dry.step(), 0.0);
+		assert_eq!(dry.ferns[0].size, 1.0);
+		assert!((dry.ferns[1].size - 2.7).abs() < 1e-12);
+		assert_eq!(dry.nitrogen, 0.2);
+	}
+
+	fn checkpoint_path(name: &str) -> std::path::PathBuf {
+		std::env::temp_dir().join(format!("fern-{}-{}.json", name, std::process::id()))
+	}
+
+	fn trajectory(ecosystem: &mut Ecosystem, days: usize) -> Vec<(f64, f64, Vec<f64>)> {
+		(0..days).map(|_| {
+			let used = ecosystem.step();
+			(used, ecosystem.nitrogen, ecosystem.ferns.iter().map(|fern| fern.size).collect())
+		}).collect()
+	}
+
+	#[test]
+	fn resumes_exactly_after_a_save() {
+		// Run long enough for the pool to run dry partway, so the proportional sharing is
+		// exercised after the round trip too.
+		let mut ecosystem = two_ferns(5.0);
+		trajectory(&mut ecosystem, 7);
+		let path = checkpoint_path("resume");
+		ecosystem.save(&path).unwrap();
+
+		let mut resumed = Ecosystem::load(&path).unwrap();
+		fs::remove_file(&path).unwrap();
+		assert_eq!(trajectory(&mut resumed, 30), trajectory(&mut ecosystem, 30));
+	}
+
+	#[test]
+	fn refuses_other_versions() {
+		let path = checkpoint_path("version");
+		fs::write(&path, r#"{"version": 2, "ecosystem": {"pools": []}}"#).unwrap();
+		match Ecosystem::load(&path) {
+			Err(CheckpointError::Version(2)) => {}
+			Err(error) => panic!("wrong error: {}", error),
+			Ok(_) => panic!("loaded a version 2 checkpoint"),
+		}
+
+		fs::write(&path, r#"{"ecosystem": {}}"#).unwrap();
+		assert!(matches!(Ecosystem::load(&path), Err(CheckpointError::Format(_))));
+		fs::remove_file(&path).unwrap();
+
+		assert!(matches!(Ecosystem::load(&path), Err(CheckpointError::Io(_))));
+	}
+}
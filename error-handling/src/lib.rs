@@ -1,22 +1,430 @@
+extern crate num_traits;
+extern crate rand;
+extern crate rayon;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+use num_traits::Float;
+use rand::Rng;
+use rand::distributions::{IndependentSample, Normal};
+
+mod ecosystem;
+
+pub use ecosystem::{CheckpointError, Ecosystem};
+use std::error::Error;
+use std::fmt;
+use rayon::prelude::*;
+
 #[allow(non_camel_case_types)]
 pub struct git_revspec {
 
 }
 
-pub struct Fern {
-	pub size: f64,
-	pub growth_rate: f64
+/// A fern, simulated in any floating-point type 'F'. Use 'Fern<f32>' to halve the memory of
+/// large batch runs; the default is 'f64'.
+#[derive(Serialize, Deserialize)]
+pub struct Fern<F = f64> {
+	pub size: F,
+	pub growth_rate: F
 }
 
-impl Fern {
+impl<F: Float> Fern<F> {
 	/// Simplate a fern growing for one day
 	pub fn grow(&mut self) {
-		self.size *= 1.0 + self.growth_rate;
+		self.size = self.size * (F::one() + self.growth_rate);
+	}
+
+	/// Like 'grow', but the fern also spends 'respiration' times its size on upkeep, so it
+	/// grows by 'growth_rate - respiration' of its size: nothing when the two are equal, and a
+	/// loss when 'respiration' is larger.
+	pub fn grow_with_respiration(&mut self, respiration: F) {
+		self.size = self.size * (F::one() + self.growth_rate - respiration);
+	}
+
+	/// Like 'grow', but with the growth slowed by crowding, as in the logistic model: the fern
+	/// grows by 'growth_rate' times its size times the fraction of 'capacity' still unused, so
+	/// it levels off as it approaches 'capacity'.
+	pub fn grow_logistic(&mut self, capacity: F) {
+		self.size = self.size + self.growth_rate * self.size * (F::one() - self.size / capacity);
+	}
+}
+
+pub fn run_simulation<F: Float>(fern: &mut Fern<F>, days: usize) {
+	for _ in 0..days {
+		fern.grow();
+	}
+}
+
+/// The error returned when asked to simulate seasons that last no days at all.
+#[derive(Debug, PartialEq)]
+pub struct EmptySeasonError;
+
+impl fmt::Display for EmptySeasonError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "seasons must be at least one day long")
+	}
+}
+
+impl Error for EmptySeasonError {}
+
+/// Like 'run_simulation', but with the year split into alternating blocks of 'season_length'
+/// days: a growing season, in which the fern grows at its usual rate, then a dormant one, in
+/// which it doesn't grow at all, then another growing season, and so on.
+pub fn run_simulation_seasonal<F: Float>(fern: &mut Fern<F>, days: usize, season_length: usize)
+	-> Result<(), EmptySeasonError>
+{
+	if season_length == 0 {
+		return Err(EmptySeasonError);
+	}
+	for day in 0..days {
+		if (day / season_length).is_multiple_of(2) {
+			fern.grow();
+		}
 	}
+	Ok(())
 }
 
-pub fn run_simulation(fern: &mut Fern, days: usize) {
+/// Run a separate simulation for each growth rate in 'rates', all starting from 'initial_size',
+/// and return each rate paired with the fern's size after 'days' days. The simulations run in
+/// parallel, but the results come back in the same order as 'rates'.
+pub fn sweep_growth_rates(initial_size: f64, rates: &[f64], days: usize) -> Vec<(f64, f64)> {
+	rates.par_iter()
+		.map(|&growth_rate| {
+			let mut fern = Fern { size: initial_size, growth_rate };
+			run_simulation(&mut fern, days);
+			(growth_rate, fern.size)
+		})
+		.collect()
+}
+
+/// Find the growth rate that takes a fern from 'initial' to 'target' in exactly 'days' days,
+/// by bisecting on the rate: a higher rate always yields a larger fern, so we can keep halving
+/// the interval that must contain the answer. The rate returned is within 1e-12 of exact, or
+/// within that fraction of it for rates above one.
+///
+/// Return 'None' if no rate reaches the target: if 'initial' isn't positive, 'target' is
+/// negative or infinite, or there are no days to grow in and the two sizes differ.
+pub fn solve_growth_rate(initial: f64, target: f64, days: usize) -> Option<f64> {
+	const TOLERANCE: f64 = 1e-12;
+
+	if initial <= 0.0 || target < 0.0 || !target.is_finite() {
+		return None;
+	}
+	if days == 0 {
+		return if target == initial { Some(0.0) } else { None };
+	}
+
+	let final_size = |growth_rate| {
+		let mut fern = Fern { size: initial, growth_rate };
+		run_simulation(&mut fern, days);
+		fern.size
+	};
+
+	// A rate of -1 kills the fern on the first day, so the answer can't be any lower.
+	let mut low = -1.0;
+	let mut high = 1.0;
+	while final_size(high) < target {
+		low = high;
+		high *= 2.0;
+	}
+
+	while high - low > TOLERANCE * high.max(1.0) {
+		let middle = (low + high) / 2.0;
+		if final_size(middle) < target {
+			low = middle;
+		} else {
+			high = middle;
+		}
+	}
+	Some((low + high) / 2.0)
+}
+
+/// Like 'run_simulation', but return the fern's size at the start of every day, followed by its
+/// size at the end, so the result holds 'days + 1' entries.
+pub fn record_simulation<F: Float>(fern: &mut Fern<F>, days: usize) -> Vec<F> {
+	let mut history = Vec::with_capacity(days + 1);
+	history.push(fern.size);
 	for _ in 0..days {
 		fern.grow();
+		history.push(fern.size);
+	}
+	history
+}
+
+/// Grow two ferns from 'initial' at 'rate', one exponentially with 'grow' and one logistically
+/// with 'grow_logistic' towards 'capacity', and return their sizes side by side, exponential
+/// first, at the start of every day and at the end, like 'record_simulation'.
+///
+/// While the ferns are small next to 'capacity' the two hardly differ; after that the logistic
+/// one falls further and further behind.
+pub fn dual_simulate(initial: f64, rate: f64, capacity: f64, days: usize) -> Vec<(f64, f64)> {
+	let mut exponential = Fern { size: initial, growth_rate: rate };
+	let mut logistic = Fern { size: initial, growth_rate: rate };
+	let mut sizes = Vec::with_capacity(days + 1);
+	sizes.push((exponential.size, logistic.size));
+	for _ in 0..days {
+		exponential.grow();
+		logistic.grow_logistic(capacity);
+		sizes.push((exponential.size, logistic.size));
+	}
+	sizes
+}
+
+/// Return the day on which 'history', as recorded by 'record_simulation', grew the most: the
+/// 'i' for which 'history[i + 1] - history[i]' is largest, or the first such 'i' if several
+/// tie. Under logistic growth this is the inflection point, where the rate of growth peaks.
+///
+/// Return 'None' if 'history' has fewer than two entries, and so no days of growth.
+pub fn peak_growth_day(history: &[f64]) -> Option<usize> {
+	let mut peak: Option<(usize, f64)> = None;
+	for (day, pair) in history.windows(2).enumerate() {
+		let increase = pair[1] - pair[0];
+		match peak {
+			Some((_, largest)) if increase <= largest => {}
+			_ => peak = Some((day, increase)),
+		}
+	}
+	peak.map(|(day, _)| day)
+}
+
+/// Draw 'history' as a line of block characters, one per entry, scaled so that the smallest
+/// entry is the lowest block and the largest is the highest. A series with no variation at all
+/// is drawn entirely in the lowest block.
+pub fn sparkline(history: &[f64]) -> String {
+	const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+	let min = history.iter().cloned().fold(f64::INFINITY, f64::min);
+	let max = history.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+	history.iter()
+		.map(|&size| {
+			if max > min {
+				let level = (size - min) / (max - min) * (BLOCKS.len() - 1) as f64;
+				BLOCKS[level.round() as usize]
+			} else {
+				BLOCKS[0]
+			}
+		})
+		.collect()
+}
+
+/// Simulate 'runs' ferns for 'days' days, each starting out like 'template' but growing each
+/// day at a rate drawn afresh from a normal distribution around the template's growth rate,
+/// with the given 'variance'. Return, for the start of the first day and the end of every day,
+/// the day number, the mean size across the runs, and the 5th and 95th percentiles of size,
+/// so the result holds 'days + 1' entries like 'record_simulation'.
+///
+/// The percentiles are by nearest rank. Return an empty vector if 'runs' is zero.
+///
+/// Panics if 'variance' is negative or NaN.
+pub fn simulate_ensemble<R: Rng>(template: &Fern, runs: usize, days: usize, variance: f64,
+								 rng: &mut R) -> Vec<(usize, f64, f64, f64)> {
+	assert!(variance >= 0.0,
+			"the variance of the growth rate must be at least zero, but was {}", variance);
+	if runs == 0 {
+		return Vec::new();
+	}
+
+	let rates = Normal::new(template.growth_rate, variance.sqrt());
+	let mut ferns: Vec<Fern> = (0..runs)
+		.map(|_| Fern { size: template.size, growth_rate: template.growth_rate })
+		.collect();
+	let mut bands = Vec::with_capacity(days + 1);
+	for day in 0..=days {
+		if day > 0 {
+			for fern in &mut ferns {
+				fern.growth_rate = rates.ind_sample(rng);
+				fern.grow();
+			}
+		}
+
+		let mut sizes: Vec<f64> = ferns.iter().map(|fern| fern.size).collect();
+		sizes.sort_by(|a, b| a.partial_cmp(b).unwrap());
+		let percentile = |p: f64| sizes[((p * runs as f64).ceil() as usize).max(1) - 1];
+		let mean = sizes.iter().sum::<f64>() / runs as f64;
+		bands.push((day, mean, percentile(0.05), percentile(0.95)));
+	}
+	bands
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use rand::{SeedableRng, XorShiftRng};
+
+	#[test]
+	fn grows_in_f32_and_f64() {
+		let mut small = Fern { size: 1.0f32, growth_rate: 0.5 };
+		run_simulation(&mut small, 2);
+		assert_eq!(small.size, 2.25f32);
+
+		let mut large = Fern { size: 1.0f64, growth_rate: 0.5 };
+		run_simulation(&mut large, 2);
+		assert_eq!(large.size, 2.25f64);
+
+		let mut slow: Fern = Fern { size: 1.0, growth_rate: 0.001 };
+		run_simulation(&mut slow, 1000);
+		assert!((slow.size - 1.001f64.powi(1000)).abs() < 1e-9);
+	}
+
+	#[test]
+	fn sweep_is_ordered_by_rate() {
+		let rates = [0.0, 0.01, 0.02, 0.05, 0.1];
+		let sweep = sweep_growth_rates(1.0, &rates, 100);
+		assert_eq!(sweep.len(), rates.len());
+		assert_eq!(sweep[0], (0.0, 1.0));
+		for (pair, &rate) in sweep.iter().zip(rates.iter()) {
+			assert_eq!(pair.0, rate);
+		}
+		for pair in sweep.windows(2) {
+			assert!(pair[1].1 > pair[0].1);
+		}
+		assert!(sweep_growth_rates(1.0, &[], 100).is_empty());
+	}
+
+	#[test]
+	fn records_every_day() {
+		let mut fern = Fern { size: 1.0, growth_rate: 1.0 };
+		assert_eq!(record_simulation(&mut fern, 3), vec![1.0, 2.0, 4.0, 8.0]);
+		assert_eq!(fern.size, 8.0);
+	}
+
+	#[test]
+	fn sparkline_follows_the_series() {
+		let mut fern = Fern { size: 1.0, growth_rate: 0.05 };
+		let line = sparkline(&record_simulation(&mut fern, 40));
+		let blocks: Vec<char> = line.chars().collect();
+		assert_eq!(blocks.len(), 41);
+		assert_eq!(blocks[0], '▁');
+		assert_eq!(blocks[40], '█');
+		for pair in blocks.windows(2) {
+			assert!(pair[0] <= pair[1]);
+		}
+
+		assert_eq!(sparkline(&[0.0, 7.0, 3.5]), "▁█▅");
+		assert_eq!(sparkline(&[2.0, 2.0, 2.0]), "▁▁▁");
+		assert_eq!(sparkline(&[]), "");
+	}
+
+	#[test]
+	fn solved_rate_reaches_the_target() {
+		for &(initial, target, days) in &[(1.0, 2.0, 100), (5.0, 1.0, 10), (1.0, 1000.0, 3), (2.0, 2.0, 7)] {
+			let growth_rate = solve_growth_rate(initial, target, days).unwrap();
+			let mut fern = Fern { size: initial, growth_rate };
+			run_simulation(&mut fern, days);
+			assert!((fern.size - target).abs() < 1e-9 * target, "{} != {}", fern.size, target);
+		}
+		assert!(solve_growth_rate(2.0, 2.0, 7).unwrap().abs() < 1e-12);
+		assert!((solve_growth_rate(1.0, 1e300, 1).unwrap() / 1e300 - 1.0).abs() < 1e-9);
+	}
+
+	#[test]
+	fn unreachable_targets_have_no_rate() {
+		assert_eq!(solve_growth_rate(1.0, -1.0, 10), None);
+		assert_eq!(solve_growth_rate(0.0, 5.0, 10), None);
+		assert_eq!(solve_growth_rate(1.0, f64::INFINITY, 10), None);
+		assert_eq!(solve_growth_rate(1.0, 5.0, 0), None);
+		assert_eq!(solve_growth_rate(1.0, 1.0, 0), Some(0.0));
+	}
+
+	#[test]
+	fn seasons_alternate_growth_and_dormancy() {
+		// Doubling for two days, then flat for two, in steps.
+		let sizes: Vec<f64> = (1..=8)
+			.map(|days| {
+				let mut fern = Fern { size: 1.0, growth_rate: 1.0 };
+				run_simulation_seasonal(&mut fern, days, 2).unwrap();
+				fern.size
+			})
+			.collect();
+		assert_eq!(sizes, vec![2.0, 4.0, 4.0, 4.0, 8.0, 16.0, 16.0, 16.0]);
+
+		let mut fern = Fern { size: 1.0, growth_rate: 1.0 };
+		assert_eq!(run_simulation_seasonal(&mut fern, 10, 0), Err(EmptySeasonError));
+		assert_eq!(fern.size, 1.0);
+	}
+
+	#[test]
+	fn finds_the_logistic_inflection() {
+		// A logistic curve with capacity 100 whose inflection falls halfway between days 50
+		// and 51, so the growth on day 50 is the largest.
+		let history: Vec<f64> = (0..=120)
+			.map(|day| 100.0 / (1.0 + (-0.2 * (day as f64 - 50.5)).exp()))
+			.collect();
+		assert_eq!(peak_growth_day(&history), Some(50));
+
+		assert_eq!(peak_growth_day(&[3.0, 3.0, 3.0]), Some(0));
+		assert_eq!(peak_growth_day(&[1.0]), None);
+		assert_eq!(peak_growth_day(&[]), None);
+	}
+
+	#[test]
+	fn respiration_offsets_growth() {
+		let mut balanced = Fern { size: 3.0, growth_rate: 0.25 };
+		for _ in 0..10 {
+			balanced.grow_with_respiration(0.25);
+		}
+		assert_eq!(balanced.size, 3.0);
+
+		let mut declining = Fern { size: 4.0, growth_rate: 0.25 };
+		declining.grow_with_respiration(0.75);
+		assert_eq!(declining.size, 2.0);
+
+		let mut growing = Fern { size: 4.0f32, growth_rate: 0.75 };
+		growing.grow_with_respiration(0.25);
+		assert_eq!(growing.size, 6.0f32);
 	}
-}
\ No newline at end of file
+
+	#[test]
+	fn ensemble_bands_widen_around_the_mean() {
+		let template = Fern { size: 1.0, growth_rate: 0.02 };
+		let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+		let bands = simulate_ensemble(&template, 500, 100, 0.0004, &mut rng);
+		assert_eq!(bands.len(), 101);
+		assert_eq!(bands[0], (0, 1.0, 1.0, 1.0));
+		for (day, &(number, mean, low, high)) in bands.iter().enumerate() {
+			assert_eq!(number, day);
+			assert!(low <= mean && mean <= high, "day {}: {} {} {}", day, low, mean, high);
+		}
+		let width = |day: usize| bands[day].3 - bands[day].2;
+		assert!(0.0 < width(10) && width(10) < width(50) && width(50) < width(100));
+
+		// The mean should stay near deterministic growth at the template's rate.
+		assert!((bands[100].1 / 1.02f64.powi(100) - 1.0).abs() < 0.05);
+
+		// The same seed gives the same bands.
+		let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+		assert_eq!(simulate_ensemble(&template, 500, 100, 0.0004, &mut rng), bands);
+		assert!(simulate_ensemble(&template, 0, 100, 0.0004, &mut rng).is_empty());
+	}
+
+	#[test]
+	#[should_panic(expected = "the variance of the growth rate must be at least zero, but was -0.0004")]
+	fn ensemble_refuses_a_negative_variance() {
+		let template = Fern { size: 1.0, growth_rate: 0.02 };
+		let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+		simulate_ensemble(&template, 500, 100, -0.0004, &mut rng);
+	}
+
+	#[test]
+	fn logistic_growth_falls_behind() {
+		let sizes = dual_simulate(1.0, 0.1, 1000.0, 100);
+		assert_eq!(sizes.len(), 101);
+		assert_eq!(sizes[0], (1.0, 1.0));
+
+		// Early on, crowding costs only a tenth of a percent or so.
+		for &(exponential, logistic) in &sizes[1..10] {
+			assert!(logistic < exponential);
+			assert!((exponential - logistic) / exponential < 0.01);
+		}
+
+		// Later the gap widens every day, and the logistic fern settles below its capacity.
+		for pair in sizes[10..].windows(2) {
+			assert!(pair[1].0 - pair[1].1 > pair[0].0 - pair[0].1);
+		}
+		let (exponential, logistic) = sizes[100];
+		assert!(exponential > 10_000.0);
+		assert!(logistic < 1000.0 && logistic > 900.0);
+	}
+}
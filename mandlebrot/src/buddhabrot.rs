@@ -0,0 +1,167 @@
+//! The Buddhabrot: instead of coloring each point by how quickly it escapes, follow the orbits
+//! of many random points that do escape, and color each pixel by how many orbit points fell in
+//! it.
+
+use image::Rgb;
+use num::Complex;
+
+use super::escape_time_visiting;
+use palette::Palette;
+use rng::SplitMix64;
+
+/// The region random points are drawn from, which holds the whole set. Points outside it
+/// escape at once, and add next to nothing.
+const SAMPLE_UPPER_LEFT: Complex<f64> = Complex { re: -2.0, im: 1.5 };
+const SAMPLE_LOWER_RIGHT: Complex<f64> = Complex { re: 1.0, im: -1.5 };
+
+/// How many samples each batch of work takes. Each batch has its own generator, seeded from the
+/// render's seed and the batch's number, so the image doesn't depend on how many threads share
+/// out the batches.
+const BATCH: usize = 10_000;
+
+/// The seed for batch number 'batch' of a render seeded with 'seed'. Scattering the batch
+/// numbers keeps the batches of neighboring seeds, like 7 and 8, from sharing generators.
+fn batch_seed(seed: u64, batch: usize) -> u64 {
+	seed ^ (batch as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+}
+
+/// If 'c' escapes within 'limit' iterations, add one to the pixel of 'density' under each
+/// point of its orbit that lands within the image, and return true. Otherwise leave 'density'
+/// alone and return false. Counts stop at 'u32::MAX' rather than wrapping.
+///
+/// The orbit is followed only once, noting the pixels it passes through in 'visited', whose
+/// contents are replaced; passing the same buffer for every point saves allocating one each.
+fn accumulate_orbit(density: &mut [u32],
+					visited: &mut Vec<usize>,
+					bounds: (usize, usize),
+					upper_left: Complex<f64>,
+					lower_right: Complex<f64>,
+					c: Complex<f64>,
+					limit: u32)
+	-> bool
+{
+	let (width, height) = (lower_right.re - upper_left.re, upper_left.im - lower_right.im);
+	visited.clear();
+	let escaped = escape_time_visiting(c, limit, |z| {
+		let column = (z.re - upper_left.re) / width * bounds.0 as f64;
+		let row = (upper_left.im - z.im) / height * bounds.1 as f64;
+		if column >= 0.0 && row >= 0.0 && (column as usize) < bounds.0 && (row as usize) < bounds.1 {
+			visited.push(row as usize * bounds.0 + column as usize);
+		}
+	}).is_some();
+	if escaped {
+		for &pixel in visited.iter() {
+			density[pixel] = density[pixel].saturating_add(1);
+		}
+	}
+	escaped
+}
+
+/// Draw 'samples' random points, seeded by 'seed', and return how many times the orbits of
+/// those that escape passed through each pixel of an image with dimensions 'bounds'.
+fn density(bounds: (usize, usize),
+		   upper_left: Complex<f64>,
+		   lower_right: Complex<f64>,
+		   samples: usize,
+		   seed: u64,
+		   limit: u32)
+	-> Vec<u32>
+{
+	let batches = samples.div_ceil(BATCH);
+	let threads = num_cpus::get().min(batches.max(1));
+	let sample_width = SAMPLE_LOWER_RIGHT.re - SAMPLE_UPPER_LEFT.re;
+	let sample_height = SAMPLE_UPPER_LEFT.im - SAMPLE_LOWER_RIGHT.im;
+	let densities: Vec<Vec<u32>> = crossbeam::scope(|spawner| {
+		let handles: Vec<_> = (0..threads).map(|thread| {
+			spawner.spawn(move || {
+				let mut density = vec![0u32; bounds.0 * bounds.1];
+				let mut visited = Vec::new();
+				for batch in (thread..batches).step_by(threads) {
+					let mut rng = SplitMix64::new(batch_seed(seed, batch));
+					let count = BATCH.min(samples - batch * BATCH);
+					for _ in 0..count {
+						let c = Complex {
+							re: SAMPLE_UPPER_LEFT.re + rng.next_f64() * sample_width,
+							im: SAMPLE_UPPER_LEFT.im - rng.next_f64() * sample_height,
+						};
+						accumulate_orbit(&mut density, &mut visited, bounds, upper_left, lower_right, c,
+										 limit);
+					}
+				}
+				density
+			})
+		}).collect();
+		handles.into_iter().map(|handle| handle.join()).collect()
+	});
+
+	let mut total = vec![0u32; bounds.0 * bounds.1];
+	for density in densities {
+		for (total, count) in total.iter_mut().zip(density) {
+			*total = total.saturating_add(count);
+		}
+	}
+	total
+}
+
+/// Render the Buddhabrot for the region with corners 'upper_left' and 'lower_right', from
+/// 'samples' random points seeded by 'seed'. The densest pixel gets the last color of
+/// 'palette', and pixels no orbit reached get the first.
+pub fn render(bounds: (usize, usize),
+			  upper_left: Complex<f64>,
+			  lower_right: Complex<f64>,
+			  samples: usize,
+			  seed: u64,
+			  limit: u32,
+			  palette: &Palette)
+	-> Vec<Rgb<u8>>
+{
+	let density = density(bounds, upper_left, lower_right, samples, seed, limit);
+	let max = density.iter().cloned().max().unwrap_or(0).max(1);
+	density.iter().map(|&count| palette.sample(count as f32 / max as f32)).collect()
+}
+
+#[cfg(test)]
+use super::escape_time;
+
+#[test]
+fn test_only_escaping_orbits_count() {
+	let bounds = (30, 30);
+	let upper_left = Complex { re: -2.0, im: 1.5 };
+	let lower_right = Complex { re: 1.0, im: -1.5 };
+	let mut density = vec![0; bounds.0 * bounds.1];
+	let mut visited = Vec::new();
+
+	// 0 and -1 are in the set, so they add nothing.
+	for &c in &[Complex { re: 0.0, im: 0.0 }, Complex { re: -1.0, im: 0.0 }] {
+		assert!(!accumulate_orbit(&mut density, &mut visited, bounds, upper_left, lower_right, c, 100));
+	}
+	assert!(density.iter().all(|&count| count == 0));
+
+	// 0.5 escapes after a handful of steps, each landing on the real axis.
+	let c = Complex { re: 0.5, im: 0.0 };
+	assert!(accumulate_orbit(&mut density, &mut visited, bounds, upper_left, lower_right, c, 100));
+	let steps = escape_time(c, 100).unwrap() as usize + 1;
+	let on_axis: u32 = (0..bounds.0).map(|column| density[15 * bounds.0 + column]).sum();
+	assert!(on_axis > 0 && on_axis as usize <= steps);
+	assert_eq!(density.iter().sum::<u32>(), on_axis);
+
+	// A pixel that has seen all the orbits it can count keeps its count.
+	let mut full = vec![u32::MAX; bounds.0 * bounds.1];
+	assert!(accumulate_orbit(&mut full, &mut visited, bounds, upper_left, lower_right, c, 100));
+	assert!(full.iter().all(|&count| count == u32::MAX));
+}
+
+#[test]
+fn test_density_is_reproducible() {
+	let bounds = (24, 24);
+	let upper_left = Complex { re: -2.0, im: 1.5 };
+	let lower_right = Complex { re: 1.0, im: -1.5 };
+	let first = density(bounds, upper_left, lower_right, 25_000, 7, 100);
+	assert!(first.iter().any(|&count| count > 0));
+	assert_eq!(density(bounds, upper_left, lower_right, 25_000, 7, 100), first);
+	assert!(density(bounds, upper_left, lower_right, 25_000, 8, 100) != first);
+	assert!(density(bounds, upper_left, lower_right, 0, 7, 100).iter().all(|&count| count == 0));
+
+	let pixels = render(bounds, upper_left, lower_right, 25_000, 7, 100, &Palette::default());
+	assert!(pixels.contains(&Rgb { data: [255, 255, 255] }));
+}
@@ -0,0 +1,177 @@
+//! Saving a partly finished render to disk, so that a long render can be picked up where it
+//! left off after being interrupted.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+
+use bincode;
+use image::Rgb;
+use num::Complex;
+
+use super::{ColorMode, ExactCorners, RenderConfig, TILE_ROWS};
+
+/// Everything that decides what a render's pixels look like. A checkpoint can only be resumed
+/// by a render whose settings match exactly.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Settings {
+	bounds: (usize, usize),
+	upper_left: (f64, f64),
+	lower_right: (f64, f64),
+	mode: ColorMode,
+	palette: Vec<[u8; 3]>,
+	limit: u32,
+	samples: usize,
+	adaptive: Option<u8>,
+	precision: usize,
+	exact_corners: Option<ExactCorners>,
+	formula: Option<String>,
+	tile_rows: usize,
+}
+
+impl Settings {
+	fn new(bounds: (usize, usize), upper_left: Complex<f64>, lower_right: Complex<f64>,
+		   config: &RenderConfig) -> Settings {
+		Settings {
+			bounds,
+			upper_left: (upper_left.re, upper_left.im),
+			lower_right: (lower_right.re, lower_right.im),
+			mode: config.mode,
+			palette: config.palette.stops().iter().map(|color| color.data).collect(),
+			limit: config.limit,
+			samples: config.samples,
+			adaptive: config.adaptive,
+			precision: config.precision,
+			exact_corners: config.exact_corners.as_deref().cloned(),
+			formula: config.formula.as_ref().map(|formula| formula.source().to_string()),
+			tile_rows: TILE_ROWS,
+		}
+	}
+}
+
+/// How far a render has got: which tiles are finished, and the pixels and escape counts
+/// computed for them. Tiles are bands of 'TILE_ROWS' rows, numbered from the top; the last may
+/// be shorter.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Progress {
+	settings: Settings,
+	/// Whether each tile has been rendered.
+	done: Vec<bool>,
+	/// The escape count of each pixel, or 'None' for pixels in unfinished tiles.
+	field: Vec<Option<u32>>,
+	/// The color of each pixel, black in unfinished tiles.
+	pixels: Vec<[u8; 3]>,
+}
+
+impl Progress {
+	/// A render that hasn't started yet.
+	pub fn new(bounds: (usize, usize), upper_left: Complex<f64>, lower_right: Complex<f64>,
+			   config: &RenderConfig) -> Progress {
+		Progress {
+			settings: Settings::new(bounds, upper_left, lower_right, config),
+			done: vec![false; bounds.1.div_ceil(TILE_ROWS)],
+			field: vec![None; bounds.0 * bounds.1],
+			pixels: vec![[0, 0, 0]; bounds.0 * bounds.1],
+		}
+	}
+
+	/// Read a checkpoint written by 'save', checking that it was made by a render with the
+	/// given settings and holds as many tiles and pixels as those settings call for.
+	pub fn load(filename: &str,
+				bounds: (usize, usize),
+				upper_left: Complex<f64>,
+				lower_right: Complex<f64>,
+				config: &RenderConfig)
+		-> Result<Progress, String>
+	{
+		let file = File::open(filename)
+			.map_err(|error| format!("error opening checkpoint '{}': {}", filename, error))?;
+		let progress: Progress = bincode::deserialize_from(BufReader::new(file))
+			.map_err(|error| format!("error reading checkpoint '{}': {}", filename, error))?;
+
+		if progress.settings != Settings::new(bounds, upper_left, lower_right, config) {
+			return Err(format!("checkpoint '{}' was made by a render with different settings",
+							   filename));
+		}
+		let pixels = bounds.0 * bounds.1;
+		if progress.done.len() != bounds.1.div_ceil(TILE_ROWS) || progress.field.len() != pixels
+			|| progress.pixels.len() != pixels {
+			return Err(format!("checkpoint '{}' is damaged: it doesn't cover the whole image", filename));
+		}
+		Ok(progress)
+	}
+
+	/// Write the progress to the file named 'filename'. The file is replaced all at once, so
+	/// being interrupted while saving leaves the previous checkpoint intact.
+	pub fn save(&self, filename: &str) -> io::Result<()> {
+		let partial = format!("{}.partial", filename);
+		bincode::serialize_into(BufWriter::new(File::create(&partial)?), self)
+			.map_err(|error| io::Error::other(error.to_string()))?;
+		fs::rename(&partial, filename)
+	}
+
+	pub fn is_done(&self, tile: usize) -> bool {
+		self.done[tile]
+	}
+
+	pub fn tiles(&self) -> usize {
+		self.done.len()
+	}
+
+	/// Store the pixels and escape counts computed for 'tile', and mark it finished.
+	pub fn record(&mut self, tile: usize, pixels: &[Rgb<u8>], field: &[Option<u32>]) {
+		let start = tile * TILE_ROWS * self.settings.bounds.0;
+		for (stored, pixel) in self.pixels[start..].iter_mut().zip(pixels) {
+			*stored = pixel.data;
+		}
+		self.field[start..start + field.len()].copy_from_slice(field);
+		self.done[tile] = true;
+	}
+
+	/// Give up the pixels and escape field, finished or not.
+	pub fn into_image(self) -> (Vec<Rgb<u8>>, Vec<Option<u32>>) {
+		(self.pixels.into_iter().map(|data| Rgb { data }).collect(), self.field)
+	}
+}
+
+#[cfg(test)]
+use super::{render_image, render_tiles};
+
+#[test]
+fn test_resume_matches_uninterrupted_render() {
+	let bounds = (30, 4 * TILE_ROWS + 5);
+	let upper_left = Complex { re: -2.0, im: 1.2 };
+	let lower_right = Complex { re: 1.0, im: -1.2 };
+	let config = RenderConfig { limit: 100, ..RenderConfig::default() };
+	let whole = render_image(bounds, upper_left, lower_right, &config);
+
+	// Pretend the render was interrupted after finishing every other tile.
+	let mut progress = Progress::new(bounds, upper_left, lower_right, &config);
+	assert_eq!(progress.tiles(), 5);
+	let tile_pixels = TILE_ROWS * bounds.0;
+	for tile in (0..progress.tiles()).step_by(2) {
+		let start = tile * tile_pixels;
+		let end = (start + tile_pixels).min(whole.pixels.len());
+		progress.record(tile, &whole.pixels[start..end], &whole.field[start..end]);
+	}
+
+	let filename = std::env::temp_dir().join(format!("mandelbrot-test-resume-{}.bin", std::process::id()));
+	let filename = filename.to_str().unwrap();
+	progress.save(filename).unwrap();
+	let loaded = Progress::load(filename, bounds, upper_left, lower_right, &config).unwrap();
+	assert_eq!(loaded, progress);
+
+	let resumed = render_tiles(loaded, bounds, upper_left, lower_right, &config, None, 2);
+	assert_eq!(resumed.tile_times.len(), 2);
+	assert!(resumed.pixels == whole.pixels);
+	assert_eq!(resumed.field, whole.field);
+
+	let deeper = RenderConfig { limit: 200, ..config.clone() };
+	assert!(Progress::load(filename, bounds, upper_left, lower_right, &deeper).is_err());
+
+	// The right settings aren't enough if the rest has been cut short.
+	let mut short = Progress::new(bounds, upper_left, lower_right, &config);
+	short.field.truncate(bounds.0);
+	short.save(filename).unwrap();
+	assert!(Progress::load(filename, bounds, upper_left, lower_right, &config).is_err());
+	fs::remove_file(filename).unwrap();
+}
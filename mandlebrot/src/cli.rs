@@ -0,0 +1,756 @@
+//! Parsing the command line into a 'Command'.
+//!
+//! The first argument names a subcommand, and everything after it is a '--flag value' pair or a
+//! bare '--switch'. Every flag has a default, except where a subcommand can't do without it.
+
+use std::slice;
+use std::str::FromStr;
+use std::time::Duration;
+
+use num::Complex;
+
+use super::{AlphaMode, ColorMode, ExactCorners, F64_PRECISION, RenderConfig, parse_pair, parse_complex, parse_rgb,
+			parse_color_mode};
+use formula::Formula;
+use palette::Palette;
+use image::Rgb;
+
+pub const USAGE: &str = "\
+Usage: mandelbrot SUBCOMMAND [FLAGS]
+
+Subcommands:
+    render          Render a region of the set to a PNG file
+        --output FILE         where to write the image (mandelbrot.png)
+        --size WxH            image dimensions in pixels (800x600)
+        --upper-left RE,IM    upper-left corner on the complex plane (-2.0,1.2)
+        --lower-right RE,IM   lower-right corner on the complex plane (1.0,-1.2)
+        --stats               print a summary of the escape counts afterwards
+        --alpha               write an RGBA image in which the set is transparent
+        --soft-alpha          the same, but fading in the pixels nearest the boundary; not
+                              with --formula or --precision above 53
+        --report FILE         write the render's timings and settings to FILE as JSON
+        --contours N,N,...    draw lines where the escape count crosses each of these levels
+        --contour-color R,G,B color of the contour lines (255,0,0)
+        --buddhabrot N        instead, draw the Buddhabrot: follow the orbits of N random
+                              points that escape, and color pixels by how many passed through
+        --seed N              the seed for --buddhabrot's random points (0)
+        --diff-limit N        instead of the image, write how it differs from one rendered
+                              with this iteration limit
+        --diff-mode MODE      the same, but comparing against this coloring mode; may be
+                              combined with --diff-limit
+        --checkpoint FILE     save the render's progress to FILE as it goes
+        --checkpoint-interval SECS
+                              how often to save the progress (60)
+        --resume FILE         carry on from a checkpoint saved by a render with the same
+                              settings, rendering only what it was missing
+    probe           Report how quickly a single point escapes
+        --point RE,IM         the point to test (required)
+    estimate-area   Estimate the area of the set by counting interior points
+        --resolution N        sample an NxN grid over the set's bounding box (500)
+    zoom            Render a sequence of frames zooming in on a point
+        --output PREFIX       frames are written to PREFIX-000.png, ... (zoom)
+        --size WxH            frame dimensions in pixels (800x600)
+        --center RE,IM        the point to zoom in on (required)
+        --width W             width of the first frame on the complex plane (3.0)
+        --factor F            how much each frame zooms in on the last (2.0)
+        --frames N            how many frames to render (10)
+        --gif FILE            write the frames to FILE as one animated GIF instead
+        --delay MS            how long the GIF shows each frame, in milliseconds (100)
+    serve           Render images on request over HTTP, at
+                    /mandelbrot.png?center=RE,IM&zoom=Z&size=WxH, four at a time
+        --address ADDR:PORT   where to listen (127.0.0.1:8080)
+        --max-pixels N        refuse requests for images with more pixels than this (4000000)
+
+Flags for every subcommand:
+    --limit N             iterations before deciding a point is in the set (10000)
+
+Flags for render, zoom and serve:
+    --palette NAME        'viridis', 'magma', or 'red', 'green' or 'blue' alone
+                          (a gradient from --low-color to --high-color)
+    --random-palette SEED a palette made up from SEED, the same every time for the same SEED
+    --low-color R,G,B     color of the set and of the slowest escapes (0,0,0)
+    --high-color R,G,B    color of the fastest escapes (255,255,255)
+    --mode MODE           'linear', 'smooth-shaded', or 'interior' to color the inside of
+                          the set too (linear)
+    --samples N           samples to average for each pixel (1)
+    --adaptive THRESHOLD  take all the samples only for pixels whose color differs from a
+                          neighbor's by more than THRESHOLD (0-255) in some channel, and one
+                          sample elsewhere; needs --samples above 1
+    --precision BITS      bits of precision to iterate with (53, as in an f64); more needs
+                          the high-precision feature and --mode linear, and render's
+                          corners are then read to that many bits
+    --formula EXPR        iterate EXPR, like 'conj(z)^2 + c', instead of 'z^2 + c'; may use
+                          z, c, i, numbers, + - * ^, conj() and abs(), and needs --mode linear
+
+Example: mandelbrot render --output mandel.png --size 1000x750 --upper-left -1.20,0.35 --lower-right -1,0.20";
+
+/// What the user asked us to do.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+	Render(Render),
+	Probe(Probe),
+	EstimateArea(EstimateArea),
+	Zoom(Zoom),
+	Serve(Serve),
+}
+
+/// Render one image to a file.
+#[derive(Debug, PartialEq)]
+pub struct Render {
+	pub output: String,
+	pub bounds: (usize, usize),
+	pub upper_left: Complex<f64>,
+	pub lower_right: Complex<f64>,
+	pub config: RenderConfig,
+	pub stats: bool,
+	/// If present, give the image an alpha channel made this way.
+	pub alpha: Option<AlphaMode>,
+	pub report: Option<String>,
+	/// If present, write the difference between the image and one rendered with this
+	/// configuration instead of the image itself.
+	pub diff: Option<RenderConfig>,
+	pub checkpoint: Option<Checkpoint>,
+	/// A checkpoint to pick up from, instead of starting afresh.
+	pub resume: Option<String>,
+	/// If present, draw these contours over the image.
+	pub contours: Option<Contours>,
+	/// If present, draw the Buddhabrot instead of coloring points by their escape times.
+	pub buddhabrot: Option<Buddhabrot>,
+}
+
+/// How many random points to follow for a Buddhabrot, and how to choose them.
+#[derive(Debug, PartialEq)]
+pub struct Buddhabrot {
+	pub samples: usize,
+	pub seed: u64,
+}
+
+/// Lines to draw where the escape count crosses each of 'levels'.
+#[derive(Debug, PartialEq)]
+pub struct Contours {
+	pub levels: Vec<u32>,
+	pub color: Rgb<u8>,
+}
+
+/// Where and how often to save a render's progress.
+#[derive(Debug, PartialEq)]
+pub struct Checkpoint {
+	pub filename: String,
+	pub interval: Duration,
+}
+
+/// Report the escape time of a single point.
+#[derive(Debug, PartialEq)]
+pub struct Probe {
+	pub point: Complex<f64>,
+	pub limit: u32,
+}
+
+/// Estimate the area of the set from a grid of 'resolution' by 'resolution' points.
+#[derive(Debug, PartialEq)]
+pub struct EstimateArea {
+	pub resolution: usize,
+	pub limit: u32,
+}
+
+/// Render 'frames' images centered on 'center', the first 'width' wide on the complex plane and
+/// each following one 'factor' times narrower.
+#[derive(Debug, PartialEq)]
+pub struct Zoom {
+	pub prefix: String,
+	pub bounds: (usize, usize),
+	pub center: Complex<f64>,
+	pub width: f64,
+	pub factor: f64,
+	pub frames: usize,
+	pub config: RenderConfig,
+	/// If present, write the frames to this file as an animated GIF instead of one PNG each.
+	pub gif: Option<String>,
+	/// How long the GIF shows each frame.
+	pub delay: Duration,
+}
+
+/// Answer HTTP requests for images at 'address', rendering each with 'config'.
+#[derive(Debug, PartialEq)]
+pub struct Serve {
+	pub address: String,
+	/// The most pixels a requested image may have.
+	pub max_pixels: usize,
+	pub config: RenderConfig,
+}
+
+/// Parse 'args', the command line without the program name, into a 'Command'.
+///
+/// On failure, return a message saying what was wrong; the caller should print it along with
+/// 'USAGE'.
+pub fn parse_args(args: &[String]) -> Result<Command, String> {
+	let (subcommand, rest) = match args.split_first() {
+		Some(split) => split,
+		None => return Err("no subcommand given".to_string()),
+	};
+
+	let mut flags = Flags { args: rest.iter() };
+	match subcommand.as_str() {
+		"render" => parse_render(&mut flags).map(Command::Render),
+		"probe" => parse_probe(&mut flags).map(Command::Probe),
+		"estimate-area" => parse_estimate_area(&mut flags).map(Command::EstimateArea),
+		"zoom" => parse_zoom(&mut flags).map(Command::Zoom),
+		"serve" => parse_serve(&mut flags).map(Command::Serve),
+		_ => Err(format!("unknown subcommand '{}'", subcommand)),
+	}
+}
+
+fn parse_render(flags: &mut Flags) -> Result<Render, String> {
+	let mut render = Render {
+		output: "mandelbrot.png".to_string(),
+		bounds: (800, 600),
+		upper_left: Complex { re: -2.0, im: 1.2 },
+		lower_right: Complex { re: 1.0, im: -1.2 },
+		config: RenderConfig::default(),
+		stats: false,
+		alpha: None,
+		report: None,
+		diff: None,
+		checkpoint: None,
+		resume: None,
+		contours: None,
+		buddhabrot: None,
+	};
+
+	// The corners as written, for the high-precision path.
+	let mut upper_left = ("-2".to_string(), "1.2".to_string());
+	let mut lower_right = ("1".to_string(), "-1.2".to_string());
+	let mut checkpoint = None;
+	let mut buddhabrot_samples = None;
+	let mut seed = None;
+	let mut contour_levels = None;
+	let mut contour_color = None;
+	let mut interval = None;
+	let mut diff_limit = None;
+	let mut diff_mode = None;
+	while let Some(flag) = flags.next_flag()? {
+		match flag {
+			"--output" => render.output = flags.value(flag, |s| Some(s.to_string()))?,
+			"--size" => render.bounds = flags.value(flag, parse_bounds)?,
+			"--upper-left" => {
+				let (point, text) = flags.value(flag, parse_corner)?;
+				render.upper_left = point;
+				upper_left = text;
+			}
+			"--lower-right" => {
+				let (point, text) = flags.value(flag, parse_corner)?;
+				render.lower_right = point;
+				lower_right = text;
+			}
+			"--stats" => render.stats = true,
+			"--alpha" => render.alpha = Some(AlphaMode::Hard),
+			"--soft-alpha" => render.alpha = Some(AlphaMode::Soft),
+			"--report" => render.report = Some(flags.value(flag, |s| Some(s.to_string()))?),
+			"--diff-limit" => diff_limit = Some(flags.value(flag, parse_positive)?),
+			"--diff-mode" => diff_mode = Some(flags.value(flag, parse_color_mode)?),
+			"--checkpoint" => checkpoint = Some(flags.value(flag, |s| Some(s.to_string()))?),
+			"--checkpoint-interval" => interval = Some(flags.value(flag, parse_positive)?),
+			"--resume" => render.resume = Some(flags.value(flag, |s| Some(s.to_string()))?),
+			"--contours" => contour_levels = Some(flags.value(flag, parse_levels)?),
+			"--contour-color" => contour_color = Some(flags.value(flag, parse_rgb)?),
+			"--buddhabrot" => buddhabrot_samples = Some(flags.value(flag, parse_positive)?),
+			"--seed" => seed = Some(flags.value(flag, |s| s.parse().ok())?),
+			_ => {
+				if !parse_config_flag(&mut render.config, flag, flags)? {
+					return Err(unknown_flag("render", flag));
+				}
+			}
+		}
+	}
+
+	if render.config.precision > F64_PRECISION {
+		render.config.exact_corners = Some(Box::new(ExactCorners {
+			upper_left,
+			lower_right,
+			bounds: render.bounds,
+			first_row: 0,
+		}));
+	}
+
+	// The other render shares every setting it isn't told to change, wherever they appear.
+	if diff_limit.is_some() || diff_mode.is_some() {
+		let mut against = render.config.clone();
+		against.limit = diff_limit.unwrap_or(against.limit);
+		against.mode = diff_mode.unwrap_or(against.mode);
+		render.diff = Some(against);
+	}
+
+	render.checkpoint = match (checkpoint, interval) {
+		(Some(filename), interval) => {
+			Some(Checkpoint { filename, interval: Duration::from_secs(interval.unwrap_or(60)) })
+		}
+		(None, Some(_)) => return Err("--checkpoint-interval needs a --checkpoint".to_string()),
+		(None, None) => None,
+	};
+	render.contours = match (contour_levels, contour_color) {
+		(Some(levels), color) => {
+			Some(Contours { levels, color: color.unwrap_or(Rgb { data: [255, 0, 0] }) })
+		}
+		(None, Some(_)) => return Err("--contour-color needs --contours".to_string()),
+		(None, None) => None,
+	};
+	render.buddhabrot = match (buddhabrot_samples, seed) {
+		(Some(samples), seed) => Some(Buddhabrot { samples, seed: seed.unwrap_or(0) }),
+		(None, Some(_)) => return Err("--seed needs --buddhabrot".to_string()),
+		(None, None) => None,
+	};
+	// Everything else works from escape times, which a Buddhabrot doesn't have, and of the
+	// render settings it uses only the limit and the palette.
+	let buddhabrot_config = RenderConfig {
+		palette: render.config.palette.clone(),
+		limit: render.config.limit,
+		..RenderConfig::default()
+	};
+	if render.buddhabrot.is_some() && (render.stats || render.alpha.is_some() || render.report.is_some()
+									   || render.diff.is_some() || render.checkpoint.is_some()
+									   || render.resume.is_some() || render.contours.is_some()
+									   || render.config != buddhabrot_config) {
+		return Err("--buddhabrot only works with --output, --size, the corners, --limit and \
+					the palette flags".to_string());
+	}
+	// The soft edge comes from the distance estimate for z*z + c, which says nothing about
+	// another formula's boundary.
+	if render.alpha == Some(AlphaMode::Soft) && render.config.formula.is_some() {
+		return Err("--soft-alpha can't be combined with --formula".to_string());
+	}
+	// Nor can it be estimated in an 'f64' at the zooms that need more precision than that: the
+	// pixels there are too close together for their size, or their orbits, to come out right.
+	if render.alpha == Some(AlphaMode::Soft) && render.config.precision > F64_PRECISION {
+		return Err(format!("--soft-alpha can't be combined with --precision above {}", F64_PRECISION));
+	}
+	check_config(&render.config)?;
+	if let Some(ref against) = render.diff {
+		check_config(against).map_err(|message| format!("{} (in the --diff-* render)", message))?;
+	}
+	Ok(render)
+}
+
+fn parse_probe(flags: &mut Flags) -> Result<Probe, String> {
+	let mut point = None;
+	let mut limit = RenderConfig::default().limit;
+	while let Some(flag) = flags.next_flag()? {
+		match flag {
+			"--point" => point = Some(flags.value(flag, parse_complex)?),
+			"--limit" => limit = flags.value(flag, parse_positive)?,
+			_ => return Err(unknown_flag("probe", flag)),
+		}
+	}
+
+	match point {
+		Some(point) => Ok(Probe { point, limit }),
+		None => Err("probe needs a --point".to_string()),
+	}
+}
+
+fn parse_estimate_area(flags: &mut Flags) -> Result<EstimateArea, String> {
+	let mut estimate = EstimateArea { resolution: 500, limit: RenderConfig::default().limit };
+	while let Some(flag) = flags.next_flag()? {
+		match flag {
+			"--resolution" => estimate.resolution = flags.value(flag, parse_positive)?,
+			"--limit" => estimate.limit = flags.value(flag, parse_positive)?,
+			_ => return Err(unknown_flag("estimate-area", flag)),
+		}
+	}
+	Ok(estimate)
+}
+
+fn parse_zoom(flags: &mut Flags) -> Result<Zoom, String> {
+	let mut center = None;
+	let mut zoom = Zoom {
+		prefix: "zoom".to_string(),
+		bounds: (800, 600),
+		center: Complex { re: 0.0, im: 0.0 },
+		width: 3.0,
+		factor: 2.0,
+		frames: 10,
+		config: RenderConfig::default(),
+		gif: None,
+		delay: Duration::from_millis(100),
+	};
+
+	while let Some(flag) = flags.next_flag()? {
+		match flag {
+			"--output" => zoom.prefix = flags.value(flag, |s| Some(s.to_string()))?,
+			"--size" => zoom.bounds = flags.value(flag, parse_bounds)?,
+			"--center" => center = Some(flags.value(flag, parse_point)?),
+			"--width" => zoom.width = flags.value(flag, |s| parse_positive(s).filter(|w: &f64| w.is_finite()))?,
+			"--factor" => zoom.factor = flags.value(flag, parse_positive)?,
+			"--frames" => zoom.frames = flags.value(flag, parse_positive)?,
+			"--gif" => zoom.gif = Some(flags.value(flag, |s| Some(s.to_string()))?),
+			"--delay" => zoom.delay = Duration::from_millis(flags.value(flag, parse_positive)?),
+			_ => {
+				if !parse_config_flag(&mut zoom.config, flag, flags)? {
+					return Err(unknown_flag("zoom", flag));
+				}
+			}
+		}
+	}
+
+	if zoom.gif.is_some() && (zoom.bounds.0 > u16::MAX as usize || zoom.bounds.1 > u16::MAX as usize) {
+		return Err(format!("GIF frames can be at most {} pixels on a side", u16::MAX));
+	}
+	check_config(&zoom.config)?;
+	match center {
+		Some(center) => Ok(Zoom { center, ..zoom }),
+		None => Err("zoom needs a --center".to_string()),
+	}
+}
+
+fn parse_serve(flags: &mut Flags) -> Result<Serve, String> {
+	let mut serve = Serve {
+		address: "127.0.0.1:8080".to_string(),
+		max_pixels: 4_000_000,
+		config: RenderConfig::default(),
+	};
+	while let Some(flag) = flags.next_flag()? {
+		match flag {
+			"--address" => serve.address = flags.value(flag, |s| Some(s.to_string()))?,
+			"--max-pixels" => serve.max_pixels = flags.value(flag, parse_positive)?,
+			_ => {
+				if !parse_config_flag(&mut serve.config, flag, flags)? {
+					return Err(unknown_flag("serve", flag));
+				}
+			}
+		}
+	}
+	check_config(&serve.config)?;
+	Ok(serve)
+}
+
+/// If 'flag' is one of the flags that fill in a 'RenderConfig', consume its value into 'config'
+/// and return true. Otherwise return false, leaving 'flags' untouched.
+fn parse_config_flag(config: &mut RenderConfig, flag: &str, flags: &mut Flags) -> Result<bool, String> {
+	match flag {
+		"--palette" => config.palette = flags.value(flag, Palette::named)?,
+		"--random-palette" => config.palette = Palette::random(flags.value(flag, |s| s.parse().ok())?),
+		"--low-color" => config.palette.set_first(flags.value(flag, parse_rgb)?),
+		"--high-color" => config.palette.set_last(flags.value(flag, parse_rgb)?),
+		"--mode" => config.mode = flags.value(flag, parse_color_mode)?,
+		"--samples" => config.samples = flags.value(flag, parse_positive)?,
+		"--adaptive" => config.adaptive = Some(flags.value(flag, |s| s.parse().ok())?),
+		"--limit" => config.limit = flags.value(flag, parse_positive)?,
+		"--precision" => config.precision = flags.value(flag, parse_positive)?,
+		"--formula" => {
+			let source = flags.value(flag, |s| Some(s.to_string()))?;
+			config.formula = Some(Formula::parse(&source)?);
+		}
+		_ => return Ok(false),
+	}
+	Ok(true)
+}
+
+/// Check the settings that can't be checked one flag at a time.
+fn check_config(config: &RenderConfig) -> Result<(), String> {
+	if config.precision > F64_PRECISION {
+		if !cfg!(feature = "high-precision") {
+			return Err(format!("--precision above {} needs the high-precision feature", F64_PRECISION));
+		}
+		if config.mode != ColorMode::Linear {
+			return Err(format!("--precision above {} only works with --mode linear", F64_PRECISION));
+		}
+	}
+	if config.adaptive.is_some() && config.samples == 1 {
+		return Err("--adaptive needs --samples above 1".to_string());
+	}
+	if config.formula.is_some() {
+		if config.mode != ColorMode::Linear {
+			return Err("--formula only works with --mode linear".to_string());
+		}
+		if config.precision > F64_PRECISION {
+			return Err("--formula can't be combined with --precision".to_string());
+		}
+	}
+	Ok(())
+}
+
+fn unknown_flag(subcommand: &str, flag: &str) -> String {
+	format!("unknown flag '{}' for {}", flag, subcommand)
+}
+
+/// Parse a corner like '"-1.25,0.35"', returning it both as a point and as the decimal text
+/// of its real and imaginary parts.
+fn parse_corner(s: &str) -> Option<(Complex<f64>, (String, String))> {
+	let point = parse_point(s)?;
+	let comma = s.find(',')?;
+	Some((point, (s[..comma].to_string(), s[comma + 1..].to_string())))
+}
+
+/// Parse a point like '"-0.75,0.1"', as 'parse_complex' does, but only if both its parts are
+/// finite. Infinities and NaNs have no place on the plane being rendered.
+pub fn parse_point(s: &str) -> Option<Complex<f64>> {
+	parse_complex(s).filter(|point| point.re.is_finite() && point.im.is_finite())
+}
+
+pub fn parse_bounds(s: &str) -> Option<(usize, usize)> {
+	parse_pair(s, 'x').filter(|&(width, height)| width > 0 && height > 0)
+}
+
+/// Parse a comma-separated list of escape counts, like '50,100,200'.
+fn parse_levels(s: &str) -> Option<Vec<u32>> {
+	s.split(',').map(|level| level.parse().ok()).collect()
+}
+
+/// Parse a number greater than zero.
+pub fn parse_positive<T: FromStr + PartialOrd + Default>(s: &str) -> Option<T> {
+	T::from_str(s).ok().filter(|n| *n > T::default())
+}
+
+/// The flags following a subcommand.
+struct Flags<'a> {
+	args: slice::Iter<'a, String>,
+}
+
+impl<'a> Flags<'a> {
+	/// Return the next flag, or 'None' once they have all been consumed.
+	fn next_flag(&mut self) -> Result<Option<&'a str>, String> {
+		match self.args.next() {
+			None => Ok(None),
+			Some(arg) if arg.starts_with("--") => Ok(Some(arg.as_str())),
+			Some(arg) => Err(format!("expected a flag, found '{}'", arg)),
+		}
+	}
+
+	/// Consume and parse the value of 'flag', which must follow it.
+	fn value<T, F: Fn(&str) -> Option<T>>(&mut self, flag: &str, parse: F) -> Result<T, String> {
+		match self.args.next() {
+			None => Err(format!("{} needs a value", flag)),
+			Some(value) => parse(value).ok_or_else(|| format!("error parsing {} value '{}'", flag, value)),
+		}
+	}
+}
+
+#[cfg(test)]
+fn args(line: &str) -> Vec<String> {
+	line.split_whitespace().map(|s| s.to_string()).collect()
+}
+
+#[test]
+fn test_parse_render() {
+	let command = parse_args(&args("render --output out.png --size 100x50 --upper-left -1,1 \
+									--lower-right 1,-1 --low-color 1,2,3 --mode smooth-shaded \
+									--samples 4 --limit 500 --stats --report out.json"));
+	assert_eq!(command, Ok(Command::Render(Render {
+		output: "out.png".to_string(),
+		bounds: (100, 50),
+		upper_left: Complex { re: -1.0, im: 1.0 },
+		lower_right: Complex { re: 1.0, im: -1.0 },
+		config: RenderConfig {
+			mode: ColorMode::SmoothShaded,
+			palette: Palette::gradient(Rgb { data: [1, 2, 3] }, Rgb { data: [255, 255, 255] }),
+			samples: 4,
+			adaptive: None,
+			limit: 500,
+			precision: F64_PRECISION,
+			exact_corners: None,
+			formula: None,
+		},
+		stats: true,
+		alpha: None,
+		report: Some("out.json".to_string()),
+		diff: None,
+		checkpoint: None,
+		resume: None,
+		contours: None,
+		buddhabrot: None,
+	})));
+
+	match parse_args(&args("render --checkpoint-interval 5 --checkpoint out.bin --resume old.bin")) {
+		Ok(Command::Render(render)) => {
+			assert_eq!(render.checkpoint, Some(Checkpoint {
+				filename: "out.bin".to_string(),
+				interval: Duration::from_secs(5),
+			}));
+			assert_eq!(render.resume, Some("old.bin".to_string()));
+		}
+		other => panic!("expected a render, got {:?}", other),
+	}
+
+	match parse_args(&args("render --contour-color 0,0,255 --contours 50,100,200")) {
+		Ok(Command::Render(render)) => {
+			assert_eq!(render.contours, Some(Contours {
+				levels: vec![50, 100, 200],
+				color: Rgb { data: [0, 0, 255] },
+			}));
+		}
+		other => panic!("expected a render, got {:?}", other),
+	}
+	assert!(parse_args(&args("render --contours 50,,200")).is_err());
+	assert!(parse_args(&args("render --contour-color 0,0,255")).is_err());
+
+	match parse_args(&args("render --random-palette 7 --high-color 1,2,3")) {
+		Ok(Command::Render(render)) => {
+			let mut palette = Palette::random(7);
+			palette.set_last(Rgb { data: [1, 2, 3] });
+			assert_eq!(render.config.palette, palette);
+		}
+		other => panic!("expected a render, got {:?}", other),
+	}
+
+	match parse_args(&args("render --buddhabrot 1000 --seed 3")) {
+		Ok(Command::Render(render)) => {
+			assert_eq!(render.buddhabrot, Some(Buddhabrot { samples: 1000, seed: 3 }));
+		}
+		other => panic!("expected a render, got {:?}", other),
+	}
+	assert!(parse_args(&args("render --seed 3")).is_err());
+	assert!(parse_args(&args("render --buddhabrot 1000 --stats")).is_err());
+	for flags in &["--mode interior", "--samples 4", "--samples 4 --adaptive 3", "--formula z^2+c"] {
+		assert!(parse_args(&args(&format!("render --buddhabrot 1000 {}", flags))).is_err(), "{}", flags);
+	}
+	assert!(parse_args(&args("render --buddhabrot 1000 --limit 50 --palette magma")).is_ok());
+
+	match parse_args(&args("render --adaptive 8 --samples 4")) {
+		Ok(Command::Render(render)) => assert_eq!(render.config.adaptive, Some(8)),
+		other => panic!("expected a render, got {:?}", other),
+	}
+
+	match parse_args(&args("render --formula conj(z)^2+c")) {
+		Ok(Command::Render(render)) => {
+			assert_eq!(render.config.formula, Some(Formula::parse("conj(z)^2+c").unwrap()));
+		}
+		other => panic!("expected a render, got {:?}", other),
+	}
+
+	match parse_args(&args("render --alpha --soft-alpha")) {
+		Ok(Command::Render(render)) => assert_eq!(render.alpha, Some(AlphaMode::Soft)),
+		other => panic!("expected a render, got {:?}", other),
+	}
+
+	match parse_args(&args("render --diff-limit 50 --palette magma --limit 20")) {
+		Ok(Command::Render(render)) => {
+			let against = render.diff.unwrap();
+			assert_eq!(against.limit, 50);
+			assert_eq!(against.palette, render.config.palette);
+			assert_eq!(render.config.limit, 20);
+		}
+		other => panic!("expected a render, got {:?}", other),
+	}
+
+	match parse_args(&args("render --high-color 9,9,9 --palette magma --low-color 1,1,1")) {
+		Ok(Command::Render(render)) => {
+			let stops = render.config.palette.stops();
+			assert_eq!(stops.len(), 9);
+			assert_eq!(stops[0], Rgb { data: [1, 1, 1] });
+			assert_eq!(stops[8], Palette::named("magma").unwrap().sample(1.0));
+		}
+		other => panic!("expected a render, got {:?}", other),
+	}
+
+	match parse_args(&args("render")) {
+		Ok(Command::Render(render)) => {
+			assert_eq!(render.output, "mandelbrot.png");
+			assert_eq!(render.config, RenderConfig::default());
+			assert!(!render.stats);
+			assert_eq!(render.diff, None);
+			assert_eq!(render.checkpoint, None);
+			assert_eq!(render.alpha, None);
+		}
+		other => panic!("expected a render, got {:?}", other),
+	}
+
+	// Corners closer together than an f64 can tell apart are kept as written.
+	if cfg!(feature = "high-precision") {
+		match parse_args(&args("render --precision 128 --size 4x2 --upper-left 0.1,0.2 \
+								--lower-right 0.1000000000000000000001,0.1999999999999999999999")) {
+			Ok(Command::Render(render)) => {
+				assert_eq!(render.upper_left, render.lower_right);
+				assert_eq!(render.config.exact_corners, Some(Box::new(ExactCorners {
+					upper_left: ("0.1".to_string(), "0.2".to_string()),
+					lower_right: ("0.1000000000000000000001".to_string(),
+								  "0.1999999999999999999999".to_string()),
+					bounds: (4, 2),
+					first_row: 0,
+				})));
+			}
+			other => panic!("expected a render, got {:?}", other),
+		}
+	}
+	match parse_args(&args("render --upper-left 0.1,0.2")) {
+		Ok(Command::Render(render)) => assert_eq!(render.config.exact_corners, None),
+		other => panic!("expected a render, got {:?}", other),
+	}
+}
+
+#[test]
+fn test_parse_other_subcommands() {
+	assert_eq!(parse_args(&args("probe --point -0.75,0.1 --limit 50")),
+			   Ok(Command::Probe(Probe { point: Complex { re: -0.75, im: 0.1 }, limit: 50 })));
+	assert_eq!(parse_args(&args("estimate-area --resolution 20")),
+			   Ok(Command::EstimateArea(EstimateArea { resolution: 20, limit: 10000 })));
+
+	match parse_args(&args("zoom --center -0.75,0.1 --frames 3 --factor 1.5 --samples 2")) {
+		Ok(Command::Zoom(zoom)) => {
+			assert_eq!(zoom.center, Complex { re: -0.75, im: 0.1 });
+			assert_eq!(zoom.frames, 3);
+			assert_eq!(zoom.factor, 1.5);
+			assert_eq!(zoom.width, 3.0);
+			assert_eq!(zoom.config.samples, 2);
+			assert_eq!(zoom.gif, None);
+		}
+		other => panic!("expected a zoom, got {:?}", other),
+	}
+
+	assert_eq!(parse_args(&args("serve --max-pixels 1000 --limit 50")), Ok(Command::Serve(Serve {
+		address: "127.0.0.1:8080".to_string(),
+		max_pixels: 1000,
+		config: RenderConfig { limit: 50, ..RenderConfig::default() },
+	})));
+	assert!(parse_args(&args("serve --max-pixels 0")).is_err());
+
+	match parse_args(&args("zoom --center 0,0 --gif zoom.gif --delay 40")) {
+		Ok(Command::Zoom(zoom)) => {
+			assert_eq!(zoom.gif, Some("zoom.gif".to_string()));
+			assert_eq!(zoom.delay, Duration::from_millis(40));
+		}
+		other => panic!("expected a zoom, got {:?}", other),
+	}
+}
+
+#[test]
+fn test_parse_errors() {
+	assert!(parse_args(&[]).is_err());
+	assert_eq!(parse_args(&args("explode")), Err("unknown subcommand 'explode'".to_string()));
+	assert_eq!(parse_args(&args("probe")), Err("probe needs a --point".to_string()));
+	assert_eq!(parse_args(&args("probe --point")), Err("--point needs a value".to_string()));
+	assert_eq!(parse_args(&args("probe --point 1,2 --mode linear")),
+			   Err("unknown flag '--mode' for probe".to_string()));
+	assert_eq!(parse_args(&args("render --size 0x10")),
+			   Err("error parsing --size value '0x10'".to_string()));
+	assert_eq!(parse_args(&args("render out.png")), Err("expected a flag, found 'out.png'".to_string()));
+	assert!(parse_args(&args("zoom --center 0,0 --factor -2")).is_err());
+	assert!(parse_args(&args("zoom --center 0,0 --gif big.gif --size 70000x10")).is_err());
+	assert!(parse_args(&args("render --precision 128 --mode smooth-shaded")).is_err());
+	assert!(parse_args(&args("render --adaptive 8")).is_err());
+	assert!(parse_args(&args("render --adaptive 256 --samples 4")).is_err());
+	assert_eq!(parse_args(&args("render --formula z^2+x")),
+			   Err("unknown variable 'x' in formula".to_string()));
+	assert!(parse_args(&args("render --formula conj(z)^2+c --mode interior")).is_err());
+	assert_eq!(parse_args(&args("render --formula conj(z)^2+c --diff-mode smooth-shaded")),
+			   Err("--formula only works with --mode linear (in the --diff-* render)".to_string()));
+	assert_eq!(parse_args(&args("render --formula conj(z)^2+c --soft-alpha")),
+			   Err("--soft-alpha can't be combined with --formula".to_string()));
+	assert!(parse_args(&args("render --formula conj(z)^2+c --alpha")).is_ok());
+	assert_eq!(parse_args(&args("render --precision 128 --soft-alpha")),
+			   Err("--soft-alpha can't be combined with --precision above 53".to_string()));
+	assert_eq!(parse_args(&args("render --precision 128 --alpha")).is_ok(), cfg!(feature = "high-precision"));
+	assert_eq!(parse_args(&args("render --precision 128")).is_ok(), cfg!(feature = "high-precision"));
+	// The high-precision path only colors linearly, so the other render mustn't ask otherwise.
+	let expected = if cfg!(feature = "high-precision") {
+		"--precision above 53 only works with --mode linear (in the --diff-* render)"
+	} else {
+		"--precision above 53 needs the high-precision feature"
+	};
+	assert_eq!(parse_args(&args("render --precision 128 --diff-mode interior")), Err(expected.to_string()));
+	assert_eq!(parse_args(&args("render --upper-left inf,0")),
+			   Err("error parsing --upper-left value 'inf,0'".to_string()));
+	assert!(parse_args(&args("render --precision 128 --lower-right 1,NaN")).is_err());
+	assert!(parse_args(&args("zoom --center -inf,0")).is_err());
+	assert!(parse_args(&args("zoom --center 0,0 --width inf")).is_err());
+	assert_eq!(parse_args(&args("render --checkpoint-interval 10")),
+			   Err("--checkpoint-interval needs a --checkpoint".to_string()));
+}
@@ -0,0 +1,294 @@
+//! Iteration functions given on the command line, like '"z^2 + c"', for rendering fractals
+//! other than the Mandelbrot set.
+//!
+//! A formula is an expression over the variables 'z' and 'c' and the imaginary unit 'i', built
+//! from numbers, '+', '-', '*', '^' with a whole-number exponent, parentheses, and the
+//! functions 'conj', the complex conjugate, and 'abs', which takes the absolute value of the
+//! real and imaginary parts separately, as in the burning ship fractal.
+
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+use std::sync::Arc;
+
+use num::Complex;
+
+type Function = Arc<dyn Fn(Complex<f64>, Complex<f64>) -> Complex<f64> + Send + Sync>;
+
+/// A parsed formula, ready to evaluate. Formulas compare equal if their sources do.
+#[derive(Clone)]
+pub struct Formula {
+	source: String,
+	function: Function,
+}
+
+impl Formula {
+	/// Parse 'source', or return a message saying what is wrong with it.
+	pub fn parse(source: &str) -> Result<Formula, String> {
+		let mut parser = Parser { chars: source.chars().peekable() };
+		let function = parser.sum()?;
+		match parser.peek() {
+			None => Ok(Formula { source: source.to_string(), function }),
+			Some(c) => Err(format!("unexpected '{}' in formula", c)),
+		}
+	}
+
+	pub fn source(&self) -> &str {
+		&self.source
+	}
+
+	/// Compute the next 'z' from the current 'z' and 'c'.
+	pub fn apply(&self, z: Complex<f64>, c: Complex<f64>) -> Complex<f64> {
+		(self.function)(z, c)
+	}
+
+	/// Like 'escape_time', but iterating this formula instead of 'z*z + c'.
+	pub fn escape_time(&self, c: Complex<f64>, limit: u32) -> Option<u32> {
+		let mut z = Complex { re: 0.0, im: 0.0 };
+		for i in 0..limit {
+			z = self.apply(z, c);
+			if z.norm_sqr() > 4.0 {
+				return Some(i);
+			}
+		}
+		None
+	}
+}
+
+impl fmt::Debug for Formula {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "Formula({:?})", self.source)
+	}
+}
+
+impl PartialEq for Formula {
+	fn eq(&self, other: &Formula) -> bool {
+		self.source == other.source
+	}
+}
+
+/// A recursive-descent parser that turns each piece of the formula into a closure as it goes,
+/// so that evaluating the result never looks at the text again.
+struct Parser<'a> {
+	chars: Peekable<Chars<'a>>,
+}
+
+impl<'a> Parser<'a> {
+	fn skip_whitespace(&mut self) {
+		while self.chars.peek().is_some_and(|c| c.is_whitespace()) {
+			self.chars.next();
+		}
+	}
+
+	/// Return the next character that isn't whitespace, without consuming it.
+	fn peek(&mut self) -> Option<char> {
+		self.skip_whitespace();
+		self.chars.peek().cloned()
+	}
+
+	fn expect(&mut self, expected: char) -> Result<(), String> {
+		match self.peek() {
+			Some(c) if c == expected => {
+				self.chars.next();
+				Ok(())
+			}
+			Some(c) => Err(format!("expected '{}' in formula, found '{}'", expected, c)),
+			None => Err(format!("expected '{}' at the end of the formula", expected)),
+		}
+	}
+
+	/// sum = product (('+' | '-') product)*
+	fn sum(&mut self) -> Result<Function, String> {
+		let mut left = self.product()?;
+		while let Some(op) = self.peek().filter(|&c| c == '+' || c == '-') {
+			self.chars.next();
+			let right = self.product()?;
+			left = if op == '+' {
+				Arc::new(move |z, c| left(z, c) + right(z, c))
+			} else {
+				Arc::new(move |z, c| left(z, c) - right(z, c))
+			};
+		}
+		Ok(left)
+	}
+
+	/// product = unary ('*' unary)*
+	fn product(&mut self) -> Result<Function, String> {
+		let mut left = self.unary()?;
+		while self.peek() == Some('*') {
+			self.chars.next();
+			let right = self.unary()?;
+			left = Arc::new(move |z, c| left(z, c) * right(z, c));
+		}
+		Ok(left)
+	}
+
+	/// unary = '-' unary | power
+	///
+	/// Negation binds more loosely than '^', so '-z^2' is '-(z^2)', as in ordinary notation.
+	fn unary(&mut self) -> Result<Function, String> {
+		if self.peek() == Some('-') {
+			self.chars.next();
+			let operand = self.unary()?;
+			return Ok(Arc::new(move |z, c| -operand(z, c)));
+		}
+		self.power()
+	}
+
+	/// power = atom ('^' whole-number)?
+	fn power(&mut self) -> Result<Function, String> {
+		let base = self.atom()?;
+		if self.peek() != Some('^') {
+			return Ok(base);
+		}
+		self.chars.next();
+		let exponent = self.number()?;
+		if exponent.fract() != 0.0 || !(0.0..=64.0).contains(&exponent) {
+			return Err(format!("exponent {} must be a whole number from 0 to 64", exponent));
+		}
+		let exponent = exponent as u32;
+		Ok(Arc::new(move |z, c| power(base(z, c), exponent)))
+	}
+
+	/// atom = number | variable | function '(' sum ')' | '(' sum ')'
+	fn atom(&mut self) -> Result<Function, String> {
+		match self.peek() {
+			Some('(') => {
+				self.chars.next();
+				let inner = self.sum()?;
+				self.expect(')')?;
+				Ok(inner)
+			}
+			Some(c) if c.is_ascii_digit() || c == '.' => {
+				let value = self.number()?;
+				Ok(Arc::new(move |_, _| Complex { re: value, im: 0.0 }))
+			}
+			Some(c) if c.is_alphabetic() => {
+				let mut name = String::new();
+				while let Some(&c) = self.chars.peek().filter(|c| c.is_alphanumeric()) {
+					name.push(c);
+					self.chars.next();
+				}
+				if self.peek() == Some('(') {
+					self.call(&name)
+				} else {
+					variable(&name)
+				}
+			}
+			Some(c) => Err(format!("unexpected '{}' in formula", c)),
+			None => Err("formula ends too soon".to_string()),
+		}
+	}
+
+	/// Parse the parenthesized argument of the function 'name'.
+	fn call(&mut self, name: &str) -> Result<Function, String> {
+		let function: fn(Complex<f64>) -> Complex<f64> = match name {
+			"conj" => |w| w.conj(),
+			"abs" => |w| Complex { re: w.re.abs(), im: w.im.abs() },
+			_ => return Err(format!("unknown function '{}' in formula", name)),
+		};
+		self.expect('(')?;
+		let argument = self.sum()?;
+		self.expect(')')?;
+		Ok(Arc::new(move |z, c| function(argument(z, c))))
+	}
+
+	fn number(&mut self) -> Result<f64, String> {
+		self.skip_whitespace();
+		let mut digits = String::new();
+		while let Some(&c) = self.chars.peek().filter(|c| c.is_ascii_digit() || **c == '.') {
+			digits.push(c);
+			self.chars.next();
+		}
+		digits.parse().map_err(|_| format!("expected a number in formula, found '{}'", digits))
+	}
+}
+
+fn variable(name: &str) -> Result<Function, String> {
+	match name {
+		"z" => Ok(Arc::new(|z, _| z)),
+		"c" => Ok(Arc::new(|_, c| c)),
+		"i" => Ok(Arc::new(|_, _| Complex { re: 0.0, im: 1.0 })),
+		_ => Err(format!("unknown variable '{}' in formula", name)),
+	}
+}
+
+/// Raise 'base' to the whole-number power 'exponent' by repeated squaring.
+fn power(mut base: Complex<f64>, mut exponent: u32) -> Complex<f64> {
+	let mut result = Complex { re: 1.0, im: 0.0 };
+	while exponent > 0 {
+		if exponent & 1 == 1 {
+			result *= base;
+		}
+		base *= base;
+		exponent >>= 1;
+	}
+	result
+}
+
+#[cfg(test)]
+use super::escape_time;
+
+#[cfg(test)]
+fn grid() -> Vec<Complex<f64>> {
+	let mut points = Vec::new();
+	for row in 0..24 {
+		for column in 0..32 {
+			points.push(Complex { re: -2.2 + column as f64 * 0.1, im: -1.2 + row as f64 * 0.1 });
+		}
+	}
+	points
+}
+
+#[test]
+fn test_mandelbrot_formula() {
+	let formula = Formula::parse("z^2 + c").unwrap();
+	for &point in &grid() {
+		assert_eq!(formula.escape_time(point, 200), escape_time(point, 200), "at {}", point);
+	}
+	assert_eq!(Formula::parse("z*z+c").unwrap().apply(Complex { re: 1.0, im: 1.0 },
+													   Complex { re: 0.5, im: 0.0 }),
+			   Complex { re: 0.5, im: 2.0 });
+}
+
+#[test]
+fn test_tricorn_formula() {
+	let formula = Formula::parse("conj(z)^2 + c").unwrap();
+	for &point in &grid() {
+		let mut z = Complex { re: 0.0, im: 0.0 };
+		let mut expected = None;
+		for i in 0..200 {
+			z = z.conj() * z.conj() + point;
+			if z.norm_sqr() > 4.0 {
+				expected = Some(i);
+				break;
+			}
+		}
+		assert_eq!(formula.escape_time(point, 200), expected, "at {}", point);
+	}
+}
+
+#[test]
+fn test_formula_arithmetic() {
+	let z = Complex { re: -1.0, im: 2.0 };
+	let c = Complex { re: 0.5, im: -0.5 };
+	let apply = |source: &str| Formula::parse(source).unwrap().apply(z, c);
+	assert_eq!(apply("abs(z)"), Complex { re: 1.0, im: 2.0 });
+	assert_eq!(apply("-z + 2*i"), Complex { re: 1.0, im: 0.0 });
+	assert_eq!(apply("(z - c) * 2"), Complex { re: -3.0, im: 5.0 });
+	assert_eq!(apply("z^0"), Complex { re: 1.0, im: 0.0 });
+	assert_eq!(apply("z^3"), z * z * z);
+	assert_eq!(apply("-z^2"), -(z * z));
+	assert_eq!(apply("-z^2 + c"), apply("-(z^2) + c"));
+	assert_eq!(apply("(-z)^2"), z * z);
+}
+
+#[test]
+fn test_formula_errors() {
+	assert_eq!(Formula::parse("z^2 + x").err(), Some("unknown variable 'x' in formula".to_string()));
+	assert_eq!(Formula::parse("sin(z)").err(), Some("unknown function 'sin' in formula".to_string()));
+	assert!(Formula::parse("z^2.5 + c").is_err());
+	assert!(Formula::parse("(z + c").is_err());
+	assert!(Formula::parse("z c").is_err());
+	assert!(Formula::parse("").is_err());
+}
@@ -3,12 +3,36 @@ extern crate image;
 extern crate crossbeam;
 extern crate num_cpus;
 extern crate lerp;
+extern crate serde;
+extern crate serde_json;
+extern crate bincode;
+extern crate gif;
+#[cfg(feature = "high-precision")]
+extern crate dashu_float;
+#[macro_use]
+extern crate serde_derive;
+
+mod buddhabrot;
+mod checkpoint;
+mod cli;
+mod formula;
+mod palette;
+#[cfg(feature = "high-precision")]
+mod precise;
+mod report;
+mod rng;
+mod server;
 
 use num::Complex;
-use std::str::FromStr;  
 use std::io::Write;
-use image::{RgbImage, Rgb};
-use lerp::Lerp;
+use std::str::FromStr;  
+use std::sync::{mpsc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use image::{RgbImage, RgbaImage, Rgb, Rgba};
+use checkpoint::Progress;
+use formula::Formula;
+use palette::Palette;
+use report::RenderReport;
 
 
 /// Try to determine if 'c' is in the mandelbrot set, using at most 'limit' iterations to decide. 
@@ -16,11 +40,18 @@ use lerp::Lerp;
 /// If 'c' is not a member, return 'Some(i)', where 'i' is the number of iterations it took 
 /// for 'c' to leave the circle of radius two centered on the origin. If 'c' seems to be a member
 /// (more precisely, if we reached the iteration limit without being able to prove that 'c' is 
-///	not a member), return 'None'
+/// not a member), return 'None'
 fn escape_time(c: Complex<f64>, limit: u32) -> Option<u32> {
+	escape_time_visiting(c, limit, |_| {})
+}
+
+/// Like 'escape_time', but call 'visit' on each 'z' of the orbit along the way, up to and
+/// including the first outside the circle.
+fn escape_time_visiting<F: FnMut(Complex<f64>)>(c: Complex<f64>, limit: u32, mut visit: F) -> Option<u32> {
 	let mut z = Complex { re: 0.0, im: 0.0 };
 	for i in 0..limit {
 		z = z*z + c;
+		visit(z);
 		if z.norm_sqr() > 4.0 {
 			return Some(i);
 		}
@@ -28,6 +59,229 @@ fn escape_time(c: Complex<f64>, limit: u32) -> Option<u32> {
 	None
 }
 
+/// Everything we know about a point once it has left the circle of radius two.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Escape {
+	/// The iteration on which 'z' left the circle, as returned by 'escape_time'.
+	count: u32,
+	/// The value of 'z' on that iteration.
+	final_z: Complex<f64>,
+	/// The derivative of 'z' with respect to 'c' on that iteration.
+	final_dz: Complex<f64>,
+}
+
+/// Like 'escape_time', but also carries the final 'z' and its derivative 'dz' along with the
+/// iteration count, which the smooth and distance-estimated coloring modes need.
+fn escape_orbit(c: Complex<f64>, limit: u32) -> Option<Escape> {
+	let mut z = Complex { re: 0.0, im: 0.0 };
+	let mut dz = Complex { re: 0.0, im: 0.0 };
+	for i in 0..limit {
+		dz = z * dz * 2.0 + 1.0;
+		z = z*z + c;
+		if z.norm_sqr() > 4.0 {
+			return Some(Escape { count: i, final_z: z, final_dz: dz });
+		}
+	}
+	None
+}
+
+#[test]
+fn test_escape_orbit() {
+	assert_eq!(escape_orbit(Complex { re: 0.0, im: 0.0 }, 100), None);
+
+	// c = 3: z goes 0 -> 3 and escapes on the first iteration, with dz = 1.
+	let escape = escape_orbit(Complex { re: 3.0, im: 0.0 }, 100).unwrap();
+	assert_eq!(escape.count, 0);
+	assert_eq!(escape.final_z, Complex { re: 3.0, im: 0.0 });
+	assert_eq!(escape.final_dz, Complex { re: 1.0, im: 0.0 });
+	assert_eq!(escape_time(Complex { re: 3.0, im: 0.0 }, 100), Some(0));
+}
+
+/// The fractional iteration count of an escaped point, which varies continuously across the
+/// bands that 'escape_time' alone produces.
+fn smooth_count(escape: &Escape) -> f64 {
+	let log_z = escape.final_z.norm().ln();
+	escape.count as f64 + 1.0 - (log_z.ln() / 2.0_f64.ln())
+}
+
+/// Estimate the distance from an escaped point to the boundary of the set, using the final
+/// 'z' and 'dz' of its orbit.
+fn boundary_distance(escape: &Escape) -> f64 {
+	let z = escape.final_z.norm();
+	2.0 * z * z.ln() / escape.final_dz.norm()
+}
+
+/// What becomes of the orbit of a point, for coloring the interior of the set.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Fate {
+	/// The orbit escaped on this iteration, as returned by 'escape_time'.
+	Escapes(u32),
+	/// The orbit settled into an attracting cycle with this multiplier: the derivative of 'z'
+	/// with respect to itself once around the cycle, whose magnitude is below one, and zero at
+	/// the centers of the bulbs.
+	Cycles(Complex<f64>),
+	/// The orbit neither escaped nor settled within the iteration limit.
+	Undecided,
+}
+
+/// Like 'escape_time', but for points that don't escape, keep iterating to find the cycle the
+/// orbit has settled into and return its multiplier.
+fn orbit_fate(c: Complex<f64>, limit: u32) -> Fate {
+	/// The longest cycle we look for.
+	const MAX_PERIOD: u32 = 64;
+	/// How close the orbit must come back to where it was to count as a cycle.
+	const TOLERANCE: f64 = 1e-10;
+
+	let mut z = Complex { re: 0.0, im: 0.0 };
+	for i in 0..limit {
+		z = z*z + c;
+		if z.norm_sqr() > 4.0 {
+			return Fate::Escapes(i);
+		}
+	}
+
+	// If 'z' has settled, the orbit returns to it after one period, and the product of the
+	// derivatives '2z' along the way is the cycle's multiplier.
+	let start = z;
+	let mut multiplier = Complex { re: 1.0, im: 0.0 };
+	for _ in 0..MAX_PERIOD {
+		multiplier = multiplier * z * 2.0;
+		z = z*z + c;
+		if (z - start).norm() < TOLERANCE {
+			return Fate::Cycles(multiplier);
+		}
+	}
+	Fate::Undecided
+}
+
+#[test]
+fn test_orbit_fate() {
+	assert_eq!(orbit_fate(Complex { re: 3.0, im: 0.0 }, 100), Fate::Escapes(0));
+
+	// Between -3/4 and -5/4 the orbit settles into a two-cycle with multiplier 4(c + 1).
+	match orbit_fate(Complex { re: -0.9, im: 0.0 }, 1000) {
+		Fate::Cycles(multiplier) => assert!((multiplier.re - 0.4).abs() < 1e-6),
+		other => panic!("expected a cycle, got {:?}", other),
+	}
+
+	// On the boundary the orbit converges too slowly to settle.
+	assert_eq!(orbit_fate(Complex { re: 0.25, im: 0.0 }, 100), Fate::Undecided);
+}
+
+/// How a pixel's escape is turned into a color.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+enum ColorMode {
+	/// Blend between the two colors by the raw iteration count.
+	Linear,
+	/// Blend by the smooth iteration count, then darken pixels that lie within a few pixels of
+	/// the boundary, so filaments are shaded as well as colored.
+	SmoothShaded,
+	/// Color the exterior as 'Linear' does, and the interior by the multiplier of the cycle
+	/// each point settles into, which shows the structure of the bulbs.
+	Interior,
+}
+
+fn parse_color_mode(s: &str) -> Option<ColorMode> {
+	match s {
+		"linear" => Some(ColorMode::Linear),
+		"smooth-shaded" => Some(ColorMode::SmoothShaded),
+		"interior" => Some(ColorMode::Interior),
+		_ => None
+	}
+}
+
+#[test]
+fn test_parse_color_mode() {
+	assert_eq!(parse_color_mode("linear"), Some(ColorMode::Linear));
+	assert_eq!(parse_color_mode("smooth-shaded"), Some(ColorMode::SmoothShaded));
+	assert_eq!(parse_color_mode("interior"), Some(ColorMode::Interior));
+	assert_eq!(parse_color_mode("smooth"), None);
+}
+
+/// Sample 'palette' by the raw iteration count, as returned by 'escape_time'. Members of the
+/// set get the palette's first color.
+fn linear_color(count: Option<u32>, limit: u32, palette: &Palette) -> Rgb<u8> {
+	let scalar = match count {
+		None => 0.0,
+		Some(count) => (limit - count) as f32 / limit as f32
+	};
+	palette.sample(scalar)
+}
+
+/// Sample 'palette' by the smooth iteration count, then darken the result for points that lie
+/// close to the boundary.
+///
+/// 'pixel_size' is the width of one pixel on the complex plane; points closer to the boundary
+/// than a pixel or so are darkened towards black.
+fn smooth_shaded_color(escape: Option<Escape>, limit: u32, pixel_size: f64, palette: &Palette) -> Rgb<u8> {
+	match escape {
+		None => palette.sample(0.0),
+		Some(escape) => {
+			let smooth = (limit as f64 - smooth_count(&escape)) / limit as f64;
+			let shade = (boundary_distance(&escape) / pixel_size).sqrt();
+			darken(palette.sample(smooth as f32), shade.min(1.0) as f32)
+		}
+	}
+}
+
+/// The colormap for points that settle into a cycle, made the first time it's asked for rather
+/// than for every pixel.
+fn interior_palette() -> &'static Palette {
+	static INTERIOR: OnceLock<Palette> = OnceLock::new();
+	INTERIOR.get_or_init(|| Palette::named("magma").unwrap())
+}
+
+/// Color a point by its 'fate': escaped points as 'linear_color' does, and points that settle
+/// into a cycle from a separate interior colormap, brightest at the centers of the bulbs and
+/// darkening towards their edges. Points that never settle get 'palette''s first color, as
+/// the whole interior does in the other modes.
+fn interior_color(fate: Fate, limit: u32, palette: &Palette) -> Rgb<u8> {
+	match fate {
+		Fate::Escapes(count) => linear_color(Some(count), limit, palette),
+		Fate::Cycles(multiplier) => interior_palette().sample(1.0 - multiplier.norm().min(1.0) as f32),
+		Fate::Undecided => linear_color(None, limit, palette),
+	}
+}
+
+#[test]
+fn test_interior_color() {
+	let palette = Palette::default();
+	let flat = linear_color(None, 1000, &palette);
+	assert_eq!(interior_color(Fate::Undecided, 1000, &palette), flat);
+
+	let fate = orbit_fate(Complex { re: -0.9, im: 0.0 }, 1000);
+	let color = interior_color(fate, 1000, &palette);
+	assert!(color != flat);
+	assert!(color != interior_color(orbit_fate(Complex { re: 0.0, im: 0.0 }, 1000), 1000, &palette));
+}
+
+/// Scale 'color' towards black by 'shade', so that 1.0 leaves it as it is and 0.0 makes it black.
+fn darken(color: Rgb<u8>, shade: f32) -> Rgb<u8> {
+	let mut color = color;
+	for i in 0..3 {
+		color[i] = (color[i] as f32 * shade) as u8;
+	}
+	color
+}
+
+#[test]
+fn test_smooth_shaded_color_darkens_boundary() {
+	let palette = Palette::default();
+
+	// Two escapes with the same count and final 'z' get the same smooth color; the one whose
+	// derivative is larger lies closer to the boundary and must come out darker.
+	let final_z = Complex { re: 3.0, im: 0.0 };
+	let near = Escape { count: 20, final_z, final_dz: Complex { re: 1.0e6, im: 0.0 } };
+	let far = Escape { count: 20, final_z, final_dz: Complex { re: 1.0, im: 0.0 } };
+	assert_eq!(smooth_count(&near), smooth_count(&far));
+
+	let near = smooth_shaded_color(Some(near), 100, 0.01, &palette);
+	let far = smooth_shaded_color(Some(far), 100, 0.01, &palette);
+	assert!(near[0] < far[0]);
+	assert!(far[0] > 0);
+	assert_eq!(linear_color(Some(20), 100, &palette), Rgb { data: [204, 204, 204] });
+}
+
 /// Parse the string 's' as a coordinate pair, like '"400x600"' or "1.0,0.5"'.
 ///
 /// Specifically, 's' should have the form <left><sep><right> where <sep> is the
@@ -71,19 +325,13 @@ fn parse_triad<T: FromStr>(s: &str, separator: char) -> Option<(T, T, T)> {
 }
 
 fn parse_rgb(s: &str) -> Option<Rgb<u8>> {
-	match parse_triad(s, ',') {
-		Some(col) => Some(Rgb{ data: [col.0, col.1, col.2] }),
-		None => None
-	}
+	parse_triad(s, ',').map(|col| Rgb{ data: [col.0, col.1, col.2] })
 }
 
 
 // Parse a pair of floatin-point numbers separated by a comma as a complex number. 
 fn parse_complex(s: &str) -> Option<Complex<f64>> {
-	match parse_pair(s, ',') {
-		Some((re, im)) => Some( Complex{ re, im }),
-		None => None
-	}
+	parse_pair(s, ',').map(|(re, im)| Complex{ re, im })
 }
 
 #[test]
@@ -93,7 +341,7 @@ fn test_parse_complex() {
 }
 
 /// Given the row and column of a pixel in the output image, return the cooresponding
-///	point on the complex plane. 
+/// point on the complex plane. 
 ///
 /// 'bounds' is a pair giving the width and height o fth eimage in pixels
 /// 'pixel' is a (column, row) pair indicating a particular pixel in that image.
@@ -104,12 +352,23 @@ fn pixel_to_point(bounds: (usize, usize),
 				  upper_left: Complex<f64>,
 				  lower_right: Complex<f64>)
 	-> Complex<f64>
+{
+	subpixel_to_point(bounds, (pixel.0 as f64, pixel.1 as f64), upper_left, lower_right)
+}
+
+/// Like 'pixel_to_point', but 'pixel' may fall anywhere within a pixel's cell, so that
+/// '(10.5, 3.5)' is the center of the pixel in column 10, row 3.
+fn subpixel_to_point(bounds: (usize, usize),
+					 pixel: (f64, f64),
+					 upper_left: Complex<f64>,
+					 lower_right: Complex<f64>)
+	-> Complex<f64>
 {
 	let (width, height) = (lower_right.re - upper_left.re,
 						   upper_left.im - lower_right.im);
 	Complex {
-		re: upper_left.re + pixel.0 as f64 * width / bounds.0 as f64,
-		im: upper_left.im - pixel.1 as f64 * height / bounds.1 as f64,
+		re: upper_left.re + pixel.0 * width / bounds.0 as f64,
+		im: upper_left.im - pixel.1 * height / bounds.1 as f64,
 	}
 }
 
@@ -121,101 +380,1005 @@ fn test_pixel_to_point() {
 			   Complex{ re: -0.5, im: -0.5 });
 }
 
+/// Return the 'index'th element of the van der Corput sequence in 'base': the digits of 'index'
+/// in that base, mirrored about the radix point. The result always lies in [0, 1).
+fn radical_inverse(mut index: u32, base: u32) -> f64 {
+	let mut result = 0.0;
+	let mut scale = 1.0 / base as f64;
+	while index > 0 {
+		result += (index % base) as f64 * scale;
+		index /= base;
+		scale /= base as f64;
+	}
+	result
+}
+
+/// Return where to place 'samples' samples within a pixel's cell, as offsets from its upper-left
+/// corner in fractions of a pixel.
+///
+/// A single sample sits on the corner, just as an unsampled render does. More samples follow the
+/// Halton sequence in bases 2 and 3, which covers the cell more evenly than random jitter and is
+/// the same on every run, so supersampled renders are reproducible.
+fn sample_offsets(samples: usize) -> Vec<(f64, f64)> {
+	if samples == 1 {
+		return vec![(0.0, 0.0)];
+	}
+	(1..samples as u32 + 1)
+		.map(|index| (radical_inverse(index, 2), radical_inverse(index, 3)))
+		.collect()
+}
+
+#[test]
+fn test_sample_offsets() {
+	assert_eq!(sample_offsets(1), vec![(0.0, 0.0)]);
+
+	let expected = [(1.0 / 2.0, 1.0 / 3.0), (1.0 / 4.0, 2.0 / 3.0),
+					(3.0 / 4.0, 1.0 / 9.0), (1.0 / 8.0, 4.0 / 9.0)];
+	let offsets = sample_offsets(4);
+	assert_eq!(offsets.len(), 4);
+	for (&(x, y), &(ex, ey)) in offsets.iter().zip(expected.iter()) {
+		assert!((x - ex).abs() < 1e-12 && (y - ey).abs() < 1e-12);
+	}
+	for &(x, y) in &sample_offsets(64) {
+		assert!((0.0..1.0).contains(&x) && (0.0..1.0).contains(&y));
+	}
+}
+
+/// Settings that apply to every pixel of a render, as opposed to the region being rendered.
+#[derive(Clone, Debug, PartialEq)]
+struct RenderConfig {
+	/// How escapes are turned into colors.
+	mode: ColorMode,
+	/// The colors to use, from points in the set and points that take longest to escape at the
+	/// start, to points that escape immediately at the end.
+	palette: Palette,
+	/// The most iterations to try before deciding a point is in the set.
+	limit: u32,
+	/// How many samples to average for each pixel.
+	samples: usize,
+	/// If given, take all of 'samples' only for pixels whose first sample differs from one of
+	/// its four neighbors' by more than this much in some channel, and just the first sample
+	/// everywhere else. Most of an image is flat, so this saves most of the work of
+	/// supersampling while still smoothing the boundary of the set.
+	adaptive: Option<u8>,
+	/// How many bits of precision to iterate with. Anything up to 'F64_PRECISION' uses 'f64';
+	/// more needs the 'high-precision' feature, and only works with 'ColorMode::Linear'.
+	precision: usize,
+	/// The corners of the image as they were written, if the high-precision path should place
+	/// pixels by them. Only that path uses them.
+	exact_corners: Option<Box<ExactCorners>>,
+	/// What to iterate instead of 'z*z + c', if anything. Only works with 'ColorMode::Linear'.
+	formula: Option<Formula>,
+}
+
+/// The corners of an image in decimal, as given on the command line. Renders are also given
+/// the corners as 'f64's, but those are rounded, and at the deepest zooms they round to the
+/// same point.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct ExactCorners {
+	/// The real and imaginary parts of each corner.
+	upper_left: (String, String),
+	lower_right: (String, String),
+	/// The dimensions of the whole image between the corners.
+	bounds: (usize, usize),
+	/// The row of the whole image that a render's buffer starts at. An image is rendered a band
+	/// of rows at a time, and the corners always describe the whole of it.
+	first_row: usize,
+}
+
+impl RenderConfig {
+	/// The settings for rendering the band of the image that starts at row 'first_row'.
+	fn for_band(&self, first_row: usize) -> RenderConfig {
+		let mut config = self.clone();
+		if let Some(ref mut exact) = config.exact_corners {
+			exact.first_row = first_row;
+		}
+		config
+	}
+}
+
+/// The bits of precision in an 'f64'.
+const F64_PRECISION: usize = 53;
+
+impl Default for RenderConfig {
+	fn default() -> RenderConfig {
+		RenderConfig {
+			mode: ColorMode::Linear,
+			palette: Palette::default(),
+			limit: 10000,
+			samples: 1,
+			adaptive: None,
+			precision: F64_PRECISION,
+			exact_corners: None,
+			formula: None,
+		}
+	}
+}
+
 /// Render a rectabgle of the Mandelbrot set into a buffer of pixels
 ///
 /// The 'bounds' argument gives the width and height of the buffer 'pixels',
 /// which holds one garyscale pixel per byte. Th 'upper_left' and 'lower_right' 
 /// arguments specity points on the complex plane corresponding to the upper-left
-/// and lower-right corners of the pixel buffer. 
+/// and lower-right corners of the pixel buffer. 'config' says how to color them.
+///
+/// 'field' has the same layout as 'pixels', and receives the escape count of each pixel's first
+/// sample, so that the image can be analysed afterwards without being recomputed.
+///
+/// Return how many samples were taken. With 'config.adaptive' set, these include the first
+/// samples of the rows just above and below the buffer, so that pixels on its top and bottom
+/// edges have all their neighbors to compare with.
 fn render(pixels: &mut [Rgb<u8>],
+		  field: &mut [Option<u32>],
 		  bounds: (usize, usize),
 		  upper_left: Complex<f64>,
 		  lower_right: Complex<f64>,
-		  lower_color: Rgb<u8>,
-		  upper_color: Rgb<u8>)
+		  config: &RenderConfig)
+	-> usize
 {
 	assert!(pixels.len() == bounds.0 * bounds.1);
+	assert!(field.len() == pixels.len());
 
-	for row in 0..bounds.1 {
-		for column in 0..bounds.0 {
-			let point = pixel_to_point(bounds, (column, row), upper_left, lower_right);
+	let pixel_size = (lower_right.re - upper_left.re) / bounds.0 as f64;
+	let offsets = sample_offsets(config.samples);
+	// The high-precision corners take some work to convert, so it's done once for the buffer,
+	// not once a sample.
+	#[cfg(feature = "high-precision")]
+	let corners = if config.precision > F64_PRECISION {
+		Some(precise::Corners::new(bounds, upper_left, lower_right, config.exact_corners.as_deref(),
+								   config.precision))
+	} else {
+		None
+	};
+	let mut samples = 0;
+	let mut sample = |column: usize, row: isize, (x, y): (f64, f64)| {
+		samples += 1;
+		let position = (column as f64 + x, row as f64 + y);
+		#[cfg(feature = "high-precision")]
+		{
+			if let Some(ref corners) = corners {
+				let count = corners.escape_time(position, config.limit);
+				return (count, linear_color(count, config.limit, &config.palette));
+			}
+		}
+		let point = subpixel_to_point(bounds, position, upper_left, lower_right);
+		sample_color(point, pixel_size, config)
+	};
+
+	let width = bounds.0;
+	match config.adaptive {
+		None => {
+			for row in 0..bounds.1 {
+				for column in 0..width {
+					let mut sum = [0u32; 3];
+					for (n, &offset) in offsets.iter().enumerate() {
+						let (count, color) = sample(column, row as isize, offset);
+						if n == 0 {
+							field[row * width + column] = count;
+						}
+						add_color(&mut sum, color);
+					}
+					pixels[row * width + column] = average_color(sum, offsets.len());
+				}
+			}
+		}
+		Some(threshold) => {
+			// The first sample of every pixel, with an extra row above and below; row 'r' of
+			// the buffer is row 'r + 1' here.
+			let mut first = Vec::with_capacity(width * (bounds.1 + 2));
+			for row in -1..bounds.1 as isize + 1 {
+				for column in 0..width {
+					let (count, color) = sample(column, row, offsets[0]);
+					if row >= 0 && (row as usize) < bounds.1 {
+						field[row as usize * width + column] = count;
+					}
+					first.push(color);
+				}
+			}
+
+			for row in 0..bounds.1 {
+				for column in 0..width {
+					let here = first[(row + 1) * width + column];
+					let mut neighbors = vec![first[row * width + column],
+											 first[(row + 2) * width + column]];
+					if column > 0 {
+						neighbors.push(first[(row + 1) * width + column - 1]);
+					}
+					if column + 1 < width {
+						neighbors.push(first[(row + 1) * width + column + 1]);
+					}
+					let edge = neighbors.iter()
+						.any(|neighbor| (0..3).any(|i| here[i].abs_diff(neighbor[i]) > threshold));
 
-			let scalar: f32 = match escape_time(point, 10000) {
-				None => 0.0,
-				Some(count) => (10000.0 - count as f32) / 10000.0
+					let mut sum = [0u32; 3];
+					add_color(&mut sum, here);
+					let taken = if edge {
+						for &offset in &offsets[1..] {
+							add_color(&mut sum, sample(column, row as isize, offset).1);
+						}
+						offsets.len()
+					} else {
+						1
+					};
+					pixels[row * width + column] = average_color(sum, taken);
+				}
+			}
+		}
+	}
+	samples
+}
+
+fn add_color(sum: &mut [u32; 3], color: Rgb<u8>) {
+	for i in 0..3 {
+		sum[i] += color[i] as u32;
+	}
+}
+
+fn average_color(sum: [u32; 3], samples: usize) -> Rgb<u8> {
+	let mut pixel = Rgb { data: [0, 0, 0] };
+	for i in 0..3 {
+		pixel[i] = (sum[i] / samples as u32) as u8;
+	}
+	pixel
+}
+
+/// Compute the escape count and color of the single point 'point' on the complex plane.
+fn sample_color(point: Complex<f64>, pixel_size: f64, config: &RenderConfig) -> (Option<u32>, Rgb<u8>) {
+	match config.mode {
+		ColorMode::Linear => {
+			let count = match config.formula {
+				None => escape_time(point, config.limit),
+				Some(ref formula) => formula.escape_time(point, config.limit),
 			};
+			(count, linear_color(count, config.limit, &config.palette))
+		}
+		ColorMode::SmoothShaded => {
+			let escape = escape_orbit(point, config.limit);
+			(escape.map(|escape| escape.count),
+			 smooth_shaded_color(escape, config.limit, pixel_size, &config.palette))
+		}
+		ColorMode::Interior => {
+			let fate = orbit_fate(point, config.limit);
+			let count = match fate {
+				Fate::Escapes(count) => Some(count),
+				Fate::Cycles(_) | Fate::Undecided => None,
+			};
+			(count, interior_color(fate, config.limit, &config.palette))
+		}
+	}
+}
+
+/// A whole rendered image, with what we learned while rendering it.
+struct Rendering {
+	pixels: Vec<Rgb<u8>>,
+	/// The escape count of each pixel, as recorded by 'render'.
+	field: Vec<Option<u32>>,
+	/// How many threads rendered the image.
+	threads: usize,
+	/// How many samples were taken, as counted by 'render'. Tiles restored from a checkpoint
+	/// are left out.
+	samples: usize,
+	/// How long each tile rendered this time took, top to bottom. Tiles restored from a
+	/// checkpoint are left out.
+	tile_times: Vec<Duration>,
+	/// How long the whole image took, from start to finish.
+	total_time: Duration,
+}
+
+/// How many rows of pixels make up each tile, the unit of work handed to rendering threads and
+/// recorded in checkpoints.
+const TILE_ROWS: usize = 16;
+
+/// Render the whole image with corners 'upper_left' and 'lower_right' and dimensions 'bounds'.
+fn render_image(bounds: (usize, usize),
+				upper_left: Complex<f64>,
+				lower_right: Complex<f64>,
+				config: &RenderConfig)
+	-> Rendering
+{
+	render_image_on(num_cpus::get(), bounds, upper_left, lower_right, config)
+}
+
+/// Like 'render_image', but with only 'threads' threads, for when other renders are sharing
+/// the CPUs.
+fn render_image_on(threads: usize,
+				   bounds: (usize, usize),
+				   upper_left: Complex<f64>,
+				   lower_right: Complex<f64>,
+				   config: &RenderConfig)
+	-> Rendering
+{
+	let progress = Progress::new(bounds, upper_left, lower_right, config);
+	render_tiles(progress, bounds, upper_left, lower_right, config, None, threads)
+}
 
-			for i in 0..3 {
-				pixels[row * bounds.0 + column][i] = (lower_color[i] as f32).lerp((upper_color[i] as f32), scalar) as u8;
+/// One finished tile, on its way from a rendering thread.
+struct Tile {
+	index: usize,
+	pixels: Vec<Rgb<u8>>,
+	field: Vec<Option<u32>>,
+	samples: usize,
+	time: Duration,
+}
+
+/// Render every tile that 'progress' doesn't have yet. The tiles are queued up and taken in
+/// turn by 'threads' threads, so that slow tiles near the set don't hold everyone else up.
+///
+/// If 'checkpoint' is given, the progress is saved to it whenever its interval has passed
+/// since the last save.
+fn render_tiles(mut progress: Progress,
+				bounds: (usize, usize),
+				upper_left: Complex<f64>,
+				lower_right: Complex<f64>,
+				config: &RenderConfig,
+				checkpoint: Option<&cli::Checkpoint>,
+				threads: usize)
+	-> Rendering
+{
+	let start = Instant::now();
+	let pending: Vec<usize> = (0..progress.tiles()).filter(|&tile| !progress.is_done(tile)).collect();
+	let queue = Mutex::new(pending.into_iter());
+	let mut tile_times = Vec::new();
+	let mut samples = 0;
+
+	let (sender, receiver) = mpsc::channel();
+	crossbeam::scope(|spawner| {
+		for _ in 0..threads {
+			let sender = sender.clone();
+			let queue = &queue;
+			spawner.spawn(move || {
+				loop {
+					let next = queue.lock().unwrap().next();
+					let index = match next {
+						Some(index) => index,
+						None => break,
+					};
+					let tile_start = Instant::now();
+					let top = index * TILE_ROWS;
+					let tile_bounds = (bounds.0, TILE_ROWS.min(bounds.1 - top));
+					let tile_upper_left = pixel_to_point(bounds, (0, top), upper_left, lower_right);
+					let tile_lower_right = pixel_to_point(bounds, (bounds.0, top + tile_bounds.1),
+														  upper_left, lower_right);
+					let mut pixels = vec![Rgb { data: [0, 0, 0] }; tile_bounds.0 * tile_bounds.1];
+					let mut field = vec![None; pixels.len()];
+					let samples = render(&mut pixels, &mut field, tile_bounds, tile_upper_left,
+										 tile_lower_right, &config.for_band(top));
+					let time = tile_start.elapsed();
+					sender.send(Tile { index, pixels, field, samples, time }).unwrap();
+				}
+			});
+		}
+		drop(sender);
+
+		let mut last_save = Instant::now();
+		for tile in receiver {
+			progress.record(tile.index, &tile.pixels, &tile.field);
+			tile_times.push((tile.index, tile.time));
+			samples += tile.samples;
+			if let Some(checkpoint) = checkpoint {
+				if last_save.elapsed() >= checkpoint.interval {
+					// A checkpoint is only insurance; failing to write one is no reason to
+					// abandon the render. The next interval tries again.
+					if let Err(error) = progress.save(&checkpoint.filename) {
+						eprintln!("error writing checkpoint '{}': {}", checkpoint.filename, error);
+					}
+					last_save = Instant::now();
+				}
 			}
 		}
+	});
+
+	tile_times.sort_by_key(|&(index, _)| index);
+	let (pixels, field) = progress.into_image();
+	Rendering {
+		pixels,
+		field,
+		threads,
+		samples,
+		tile_times: tile_times.into_iter().map(|(_, time)| time).collect(),
+		total_time: start.elapsed(),
 	}
 }
 
-// /// Write the buffer 'pixels', whose dimensions are given by 'bounds', to the file named 'filename'.
-// fn write_image(filename: &str, pixels: &[u8], bounds: (usize, usize)) -> Result<(), std::io::Error> {
-// 	let output = File::create(filename)?;
+/// A summary of an escape field, to help judge whether a viewport and iteration limit are
+/// well chosen.
+#[derive(Debug, PartialEq)]
+struct EscapeStats {
+	/// How many pixels there were in all.
+	pixels: usize,
+	/// The fraction of them that seem to be members of the set.
+	interior_fraction: f64,
+	/// The escape counts of the rest, or 'None' if every pixel was interior.
+	exterior: Option<ExteriorStats>,
+}
 
-// 	let encoder = PNGEncoder::new(output);
-// 	encoder.encode(&pixels,
-// 				   bounds.0 as u32, bounds.1 as u32,
-// 				   ColorType::Gray(8))?;
+#[derive(Debug, PartialEq)]
+struct ExteriorStats {
+	mean: f64,
+	median: f64,
+	min: u32,
+	max: u32,
+}
 
-// 	Ok(())
-// }
+fn escape_stats(field: &[Option<u32>]) -> EscapeStats {
+	let mut counts: Vec<u32> = field.iter().filter_map(|&count| count).collect();
+	counts.sort();
 
-fn main() {
-	let args: Vec<String> = std::env::args().collect();
-
-	if args.len() != 7 {
-		writeln!(std::io::stderr(), "Usage: mandelbrot FILE PIXELS UPPERLEFT LOWERRIGHT LOWCOL HIGHCOL")
-			.unwrap();
-		writeln!(std::io::stderr(), "Example: {} mandel.png 1000x750 -1.20,0.25 -1,0.20", args[0])
-			.unwrap();
-		std::process::exit(1);
-	}
-
-	// fn parse_pair<T: FromStr>(s: &str, separator: char) -> Option<(T, T)> 
-	let bounds = parse_pair(&args[2], 'x').
-		expect("error parsing image dimensions");
-	let upper_left = parse_complex(&args[3])
-		.expect("error parsing upper left corner point");
-	let lower_right = parse_complex(&args[4])
-		.expect("error parsing lower-right corner point");
-	let lower_col = parse_rgb(&args[5])
-		.expect("error parsing lower color");
-	let upper_col = parse_rgb(&args[6])
-		.expect("Error parsing upper col");
-
-	// let mut img = ImageBuffer::<Rgb<u8>, Vec<u8>>::new(bounds.0 as u32, bounds.1 as u32);
-
-	let mut pixels: Vec<Rgb<u8>> = vec![Rgb{ data: [0, 0, 0] }; bounds.0 * bounds.1]; 
-
-	let threads = num_cpus::get();
-	let rows_per_band = bounds.1 / threads + 1;
-	{
-		let bands = pixels.chunks_mut(rows_per_band * bounds.0);
-		crossbeam::scope(|spawner| {
-			for(i, band) in bands.into_iter().enumerate() {
-				let top = rows_per_band * i;
-				let height = band.len() / bounds.0;
-				let band_bounds = (bounds.0, height);
-				let band_upper_left = pixel_to_point(bounds, (0, top), upper_left, lower_right);
-				let band_lower_right = 
-					pixel_to_point(bounds, (bounds.0, top + height), upper_left, lower_right);
-				spawner.spawn(move || {
-					render(band, band_bounds, band_upper_left, band_lower_right, lower_col, upper_col);
-				});
+	let interior = field.len() - counts.len();
+	let exterior = if counts.is_empty() {
+		None
+	} else {
+		let middle = counts.len() / 2;
+		let median = if counts.len().is_multiple_of(2) {
+			(counts[middle - 1] as f64 + counts[middle] as f64) / 2.0
+		} else {
+			counts[middle] as f64
+		};
+		Some(ExteriorStats {
+			mean: counts.iter().map(|&count| count as f64).sum::<f64>() / counts.len() as f64,
+			median,
+			min: counts[0],
+			max: counts[counts.len() - 1],
+		})
+	};
+
+	EscapeStats {
+		pixels: field.len(),
+		interior_fraction: if field.is_empty() { 0.0 } else { interior as f64 / field.len() as f64 },
+		exterior,
+	}
+}
+
+#[test]
+fn test_escape_stats() {
+	assert_eq!(escape_stats(&[None, Some(4), Some(1), None, Some(2), Some(3)]),
+			   EscapeStats {
+				   pixels: 6,
+				   interior_fraction: 2.0 / 6.0,
+				   exterior: Some(ExteriorStats { mean: 2.5, median: 2.5, min: 1, max: 4 }),
+			   });
+	assert_eq!(escape_stats(&[None, None]).exterior, None);
+
+	// Across the real axis from -2 to 1, the points -2, -1 and 0 are in the set, and 1 is not.
+	let config = RenderConfig { limit: 100, ..RenderConfig::default() };
+	let rendering = render_image((4, 1), Complex { re: -2.0, im: 0.0 },
+								 Complex { re: 2.0, im: -1.0 }, &config);
+	assert_eq!(escape_stats(&rendering.field).interior_fraction, 0.75);
+}
+
+#[test]
+fn test_render_supersampled() {
+	// A single pixel whose corner is in the set but most of whose cell lies far outside it.
+	let config = RenderConfig { limit: 10, ..RenderConfig::default() };
+	let upper_left = Complex { re: 0.0, im: 0.0 };
+	let lower_right = Complex { re: 4.0, im: -4.0 };
+
+	let mut single = vec![Rgb { data: [0, 0, 0] }];
+	render(&mut single, &mut [None], (1, 1), upper_left, lower_right, &config);
+	let mut multi = vec![Rgb { data: [0, 0, 0] }];
+	render(&mut multi, &mut [None], (1, 1), upper_left, lower_right,
+		   &RenderConfig { samples: 4, ..config });
+
+	assert_eq!(single[0], Rgb { data: [0, 0, 0] });
+	assert!(multi[0][0] > 0 && multi[0][0] < 255);
+}
+
+#[test]
+fn test_render_adaptive() {
+	let config = RenderConfig { limit: 100, samples: 9, adaptive: Some(16), ..RenderConfig::default() };
+	let bounds = (8, 6);
+	let render_counting = |upper_left, lower_right| {
+		let mut pixels = vec![Rgb { data: [0, 0, 0] }; bounds.0 * bounds.1];
+		let mut field = vec![None; pixels.len()];
+		let samples = render(&mut pixels, &mut field, bounds, upper_left, lower_right, &config);
+		(pixels, samples)
+	};
+	let first_samples = bounds.0 * (bounds.1 + 2);
+
+	// Deep inside the main cardioid every first sample is black, so nothing is supersampled.
+	let (flat, samples) = render_counting(Complex { re: -0.3, im: 0.1 },
+										  Complex { re: -0.2, im: 0.0 });
+	assert_eq!(samples, first_samples);
+	assert!(flat.iter().all(|&pixel| pixel == Rgb { data: [0, 0, 0] }));
+
+	// Straddling the boundary, some pixels are supersampled, but not all of them, and those
+	// come out the same as with uniform supersampling.
+	let upper_left = Complex { re: -1.0, im: 0.5 };
+	let lower_right = Complex { re: 0.5, im: -0.5 };
+	let (adaptive, samples) = render_counting(upper_left, lower_right);
+	let extra = samples - first_samples;
+	assert!(extra > 0 && extra < bounds.0 * bounds.1 * (config.samples - 1));
+	assert_eq!(extra % (config.samples - 1), 0);
+
+	let mut uniform = vec![Rgb { data: [0, 0, 0] }; bounds.0 * bounds.1];
+	let mut field = vec![None; uniform.len()];
+	let uniform_samples = render(&mut uniform, &mut field, bounds, upper_left, lower_right,
+								 &RenderConfig { adaptive: None, ..config.clone() });
+	assert_eq!(uniform_samples, bounds.0 * bounds.1 * config.samples);
+	let matching = adaptive.iter().zip(&uniform).filter(|&(a, u)| a == u).count();
+	assert!(matching >= extra / (config.samples - 1));
+}
+
+/// Return the per-channel absolute difference between two images of the same size, which is
+/// black wherever they agree.
+fn difference(first: &[Rgb<u8>], second: &[Rgb<u8>]) -> Vec<Rgb<u8>> {
+	assert!(first.len() == second.len());
+	first.iter().zip(second).map(|(a, b)| {
+		let mut pixel = Rgb { data: [0, 0, 0] };
+		for i in 0..3 {
+			pixel[i] = (a[i] as i16 - b[i] as i16).unsigned_abs() as u8;
+		}
+		pixel
+	}).collect()
+}
+
+#[test]
+fn test_difference() {
+	let bounds = (40, 30);
+	let upper_left = Complex { re: -2.0, im: 1.2 };
+	let lower_right = Complex { re: 1.0, im: -1.2 };
+	let shallow = RenderConfig { limit: 10, ..RenderConfig::default() };
+	let deep = RenderConfig { limit: 200, ..RenderConfig::default() };
+
+	let first = render_image(bounds, upper_left, lower_right, &shallow);
+	let again = render_image(bounds, upper_left, lower_right, &shallow);
+	let diff = difference(&first.pixels, &again.pixels);
+	assert!(diff.iter().all(|pixel| pixel.data == [0, 0, 0]));
+
+	// Points that escape between 10 and 200 iterations lie just outside the set, and were
+	// colored as members by the shallower render.
+	let second = render_image(bounds, upper_left, lower_right, &deep);
+	let diff = difference(&first.pixels, &second.pixels);
+	let changed = (0..diff.len())
+		.filter(|&i| diff[i].data != [0, 0, 0])
+		.filter(|&i| first.field[i].is_none() && second.field[i].is_some())
+		.count();
+	assert!(changed > 0);
+}
+
+/// Color a whole escape field with dimensions 'bounds', as a one-sample render with 'mode',
+/// 'limit' and 'palette' would have colored it, without iterating anything. This lets a
+/// palette be changed after the fact, for as long as the field is kept.
+///
+/// A field records only the first sample of each pixel, so the colors of a render that took
+/// more, with '--samples' or '--adaptive', are averages it can't reproduce; for such a field
+/// this gives the colors of its first samples alone.
+///
+/// The counts are colored by how far they got towards the limit, so that is needed alongside
+/// the palette. And a field holds only escape counts, which is all 'ColorMode::Linear' needs.
+/// The other modes also need the orbit of each point, so for them this returns 'None'.
+fn colorize(field: &[Option<u32>], bounds: (usize, usize), mode: ColorMode, limit: u32,
+			palette: &Palette)
+	-> Option<Vec<Rgb<u8>>>
+{
+	assert!(field.len() == bounds.0 * bounds.1);
+	match mode {
+		ColorMode::Linear => Some(field.iter().map(|&count| linear_color(count, limit, palette)).collect()),
+		ColorMode::SmoothShaded | ColorMode::Interior => None,
+	}
+}
+
+/// Work out the escape field a render with 'against' would record from 'field', recorded by a
+/// render with 'config', if that's possible without iterating again. It is when both take one
+/// sample per pixel and count escapes the same way, and 'against' stops no later: lowering the
+/// limit just turns the counts it no longer reaches into members of the set.
+fn reuse_field(field: &[Option<u32>], config: &RenderConfig, against: &RenderConfig)
+	-> Option<Vec<Option<u32>>>
+{
+	let same_counts = config.mode == ColorMode::Linear
+		&& config.formula == against.formula
+		&& config.precision == against.precision;
+	if config.samples != 1 || against.samples != 1 || !same_counts || against.limit > config.limit {
+		return None;
+	}
+	Some(field.iter().map(|count| count.filter(|&count| count < against.limit)).collect())
+}
+
+#[test]
+fn test_colorize() {
+	let bounds = (40, 30);
+	let upper_left = Complex { re: -2.0, im: 1.2 };
+	let lower_right = Complex { re: 1.0, im: -1.2 };
+	let config = RenderConfig { limit: 100, ..RenderConfig::default() };
+	let rendering = render_image(bounds, upper_left, lower_right, &config);
+	let field = &rendering.field;
+
+	let colorize_with = |name: &str| {
+		colorize(field, bounds, ColorMode::Linear, 100, &Palette::named(name).unwrap()).unwrap()
+	};
+	let (magma, viridis) = (colorize_with("magma"), colorize_with("viridis"));
+	assert_eq!(colorize(field, bounds, ColorMode::Linear, 100, &config.palette),
+			   Some(rendering.pixels.clone()));
+	assert!(magma != viridis);
+
+	// The palettes differ, but the shape doesn't: two pixels share a color under one just when
+	// they share a color under the other.
+	assert_eq!(magma.len(), viridis.len());
+	for i in 0..magma.len() {
+		for j in [i + 1, i + bounds.0] {
+			if j < magma.len() {
+				assert_eq!(magma[i] == magma[j], viridis[i] == viridis[j], "pixels {} and {}", i, j);
 			}
-		});
+		}
 	}
 
+	assert_eq!(colorize(field, bounds, ColorMode::SmoothShaded, 100, &config.palette), None);
+	assert_eq!(colorize(field, bounds, ColorMode::Interior, 100, &config.palette), None);
+}
+
+#[test]
+fn test_reuse_field() {
+	let bounds = (40, 30);
+	let upper_left = Complex { re: -2.0, im: 1.2 };
+	let lower_right = Complex { re: 1.0, im: -1.2 };
+	let deep = RenderConfig { limit: 200, ..RenderConfig::default() };
+	let shallow = RenderConfig { limit: 10, palette: Palette::named("magma").unwrap(), ..deep.clone() };
+	let rendering = render_image(bounds, upper_left, lower_right, &deep);
+
+	let field = reuse_field(&rendering.field, &deep, &shallow).unwrap();
+	let expected = render_image(bounds, upper_left, lower_right, &shallow);
+	assert_eq!(field, expected.field);
+	assert_eq!(colorize(&field, bounds, shallow.mode, shallow.limit, &shallow.palette),
+			   Some(expected.pixels));
+
+	assert_eq!(reuse_field(&rendering.field, &shallow, &deep), None);
+	assert_eq!(reuse_field(&rendering.field, &deep, &RenderConfig { samples: 4, ..shallow.clone() }), None);
+}
+
+/// Return which pixels of an escape field with dimensions 'bounds' lie on a contour: for each
+/// of 'levels', the pixels whose escape count is at least that level but which have a neighbor,
+/// above, below, left or right, whose count is below it. This draws each contour one pixel
+/// thick, just on the far side of the crossing. Points in the set count as escaping after
+/// infinitely many iterations, so the set's edge crosses every level.
+fn contour_mask(field: &[Option<u32>], bounds: (usize, usize), levels: &[u32]) -> Vec<bool> {
+	assert!(field.len() == bounds.0 * bounds.1);
+	let count = |column: usize, row: usize| field[row * bounds.0 + column].unwrap_or(u32::MAX);
+
+	let mut mask = vec![false; field.len()];
+	for row in 0..bounds.1 {
+		for column in 0..bounds.0 {
+			let mut neighbors = Vec::with_capacity(4);
+			if column > 0 {
+				neighbors.push(count(column - 1, row));
+			}
+			if column + 1 < bounds.0 {
+				neighbors.push(count(column + 1, row));
+			}
+			if row > 0 {
+				neighbors.push(count(column, row - 1));
+			}
+			if row + 1 < bounds.1 {
+				neighbors.push(count(column, row + 1));
+			}
+			let here = count(column, row);
+			mask[row * bounds.0 + column] = levels.iter()
+				.any(|&level| here >= level && neighbors.iter().any(|&neighbor| neighbor < level));
+		}
+	}
+	mask
+}
+
+#[test]
+fn test_contour_mask() {
+	// Two identical rows counting up from 0, so the field only varies along each scanline.
+	let bounds = (100, 2);
+	let field: Vec<Option<u32>> = (0..2).flat_map(|_| (0..100).map(Some)).collect();
+	let mask = contour_mask(&field, bounds, &[20, 50, 75]);
+	for row in 0..2 {
+		let contours: Vec<usize> = (0..100).filter(|&column| mask[row * 100 + column]).collect();
+		assert_eq!(contours, vec![20, 50, 75]);
+	}
+
+	// Levels the field never reaches, or starts above, draw nothing.
+	assert!(!contour_mask(&field, bounds, &[0, 500]).contains(&true));
+	assert_eq!(contour_mask(&[Some(3), None], (2, 1), &[10]), vec![false, true]);
+}
+
+/// Return the upper-left and lower-right corners of the region 'width' wide on the complex
+/// plane, centered on 'center', whose aspect ratio matches an image of dimensions 'bounds'.
+fn viewport(center: Complex<f64>, width: f64, bounds: (usize, usize)) -> (Complex<f64>, Complex<f64>) {
+	let height = width * bounds.1 as f64 / bounds.0 as f64;
+	(Complex { re: center.re - width / 2.0, im: center.im + height / 2.0 },
+	 Complex { re: center.re + width / 2.0, im: center.im - height / 2.0 })
+}
+
+#[test]
+fn test_viewport() {
+	assert_eq!(viewport(Complex { re: -1.0, im: 0.5 }, 2.0, (200, 100)),
+			   (Complex { re: -2.0, im: 1.0 }, Complex { re: 0.0, im: 0.0 }));
+}
+
+/// Write the buffer 'pixels', whose dimensions are given by 'bounds', to the file named 'filename'.
+fn write_image(filename: &str, pixels: &[Rgb<u8>], bounds: (usize, usize)) -> Result<(), std::io::Error> {
 	assert!(bounds.0 * bounds.1 == pixels.len());
 	let img = RgbImage::from_fn(bounds.0 as u32, bounds.1 as u32, |x, y| {
 		*pixels.get((y * bounds.0 as u32 + x) as usize).expect("Index out of range")
 	});
-	img.save("mandelbrot.png").expect("Error: Could not save PNG image");
+	img.save(filename)
+}
+
+/// Write 'frames', each a buffer of pixels with dimensions 'bounds', to 'output' as a GIF that
+/// shows each for 'delay' and loops forever.
+///
+/// GIF only allows 256 colors in a frame, so each frame gets its own palette, chosen to suit
+/// its pixels best.
+fn write_gif<W: Write>(output: W, frames: &[Vec<Rgb<u8>>], bounds: (usize, usize), delay: Duration)
+	-> Result<(), std::io::Error>
+{
+	let (width, height) = (bounds.0 as u16, bounds.1 as u16);
+	let mut encoder = gif::Encoder::new(output, width, height, &[])?;
+	encoder.write_extension(gif::ExtensionData::Repetitions(gif::Repeat::Infinite))?;
+
+	// GIF delays are in hundredths of a second.
+	let delay = (delay.as_millis() / 10).min(u16::MAX as u128) as u16;
+	for pixels in frames {
+		assert!(pixels.len() == bounds.0 * bounds.1);
+		let data: Vec<u8> = pixels.iter().flat_map(|pixel| pixel.data.to_vec()).collect();
+		let mut frame = gif::Frame::from_rgb(width, height, &data);
+		frame.delay = delay;
+		encoder.write_frame(&frame)?;
+	}
+	Ok(())
+}
+
+#[test]
+fn test_write_gif() {
+	let bounds = (20, 15);
+	let config = RenderConfig { limit: 50, ..RenderConfig::default() };
+	let frames: Vec<Vec<Rgb<u8>>> = [3.0, 1.5, 0.75].iter().map(|&width| {
+		let (upper_left, lower_right) = viewport(Complex { re: -0.75, im: 0.1 }, width, bounds);
+		render_image(bounds, upper_left, lower_right, &config).pixels
+	}).collect();
+
+	let mut output = Vec::new();
+	write_gif(&mut output, &frames, bounds, Duration::from_millis(250)).unwrap();
+
+	let mut reader = gif::Decoder::new(&output[..]).read_info().unwrap();
+	assert_eq!((reader.width(), reader.height()), (20, 15));
+	let mut delays = Vec::new();
+	while let Some(frame) = reader.read_next_frame().unwrap() {
+		delays.push(frame.delay);
+	}
+	assert_eq!(delays, vec![25, 25, 25]);
+}
+
+/// How to make an image's alpha channel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum AlphaMode {
+	/// Members of the set are transparent, and everything else opaque.
+	Hard,
+	/// Members of the set are transparent, and points outside it fade in over the pixel or so
+	/// nearest the boundary, by the same distance estimate that darkens them in the
+	/// smooth-shaded mode.
+	Soft,
+}
+
+/// Compute an alpha value for each pixel of an image rendered with 'config', whose escape
+/// field is 'field' and whose dimensions and corners are 'bounds', 'upper_left' and
+/// 'lower_right'.
+fn alpha_mask(field: &[Option<u32>],
+			  bounds: (usize, usize),
+			  upper_left: Complex<f64>,
+			  lower_right: Complex<f64>,
+			  config: &RenderConfig,
+			  mode: AlphaMode)
+	-> Vec<u8>
+{
+	assert!(field.len() == bounds.0 * bounds.1);
+	let pixel_size = (lower_right.re - upper_left.re) / bounds.0 as f64;
+	// The field holds each pixel's first sample, so estimate distances from the same point.
+	let (x, y) = sample_offsets(config.samples)[0];
+
+	field.iter().enumerate().map(|(i, count)| match (count, mode) {
+		(None, _) => 0,
+		(Some(_), AlphaMode::Hard) => 255,
+		(Some(_), AlphaMode::Soft) => {
+			let position = ((i % bounds.0) as f64 + x, (i / bounds.0) as f64 + y);
+			let point = subpixel_to_point(bounds, position, upper_left, lower_right);
+			match escape_orbit(point, config.limit) {
+				None => 0,
+				Some(escape) => {
+					let shade = (boundary_distance(&escape) / pixel_size).sqrt().min(1.0);
+					(shade * 255.0).round() as u8
+				}
+			}
+		}
+	}).collect()
+}
+
+#[test]
+fn test_alpha_mask() {
+	// A row across the real axis whose first pixel, at -2, is in the set and whose last, at
+	// 1.5, escapes at once.
+	let bounds = (8, 1);
+	let upper_left = Complex { re: -2.0, im: 0.0 };
+	let lower_right = Complex { re: 2.0, im: -0.5 };
+	let config = RenderConfig { limit: 100, ..RenderConfig::default() };
+	let rendering = render_image(bounds, upper_left, lower_right, &config);
+	assert_eq!(rendering.field[0], None);
+	assert!(rendering.field[7].is_some());
+
+	let hard = alpha_mask(&rendering.field, bounds, upper_left, lower_right, &config, AlphaMode::Hard);
+	assert_eq!(hard[0], 0);
+	assert_eq!(hard[7], 255);
+	assert!(hard.iter().all(|&alpha| alpha == 0 || alpha == 255));
+
+	let soft = alpha_mask(&rendering.field, bounds, upper_left, lower_right, &config, AlphaMode::Soft);
+	assert_eq!(soft[0], 0);
+	assert_eq!(soft[7], 255);
+
+	// Just outside the cusp at 1/4, a point is far less than a pixel this large from the
+	// boundary, so it is mostly transparent.
+	let near = alpha_mask(&[Some(30)], (1, 1), Complex { re: 0.26, im: 0.0 },
+						  Complex { re: 1.26, im: -1.0 }, &config, AlphaMode::Soft);
+	assert!(near[0] > 0 && near[0] < 128);
+}
+
+/// Like 'write_image', but with an alpha value for each pixel, saved as an RGBA PNG.
+fn write_rgba_image(filename: &str, pixels: &[Rgb<u8>], alpha: &[u8], bounds: (usize, usize))
+	-> Result<(), std::io::Error>
+{
+	assert!(bounds.0 * bounds.1 == pixels.len() && alpha.len() == pixels.len());
+	let img = RgbaImage::from_fn(bounds.0 as u32, bounds.1 as u32, |x, y| {
+		let i = (y * bounds.0 as u32 + x) as usize;
+		let Rgb { data: [r, g, b] } = pixels[i];
+		Rgba { data: [r, g, b, alpha[i]] }
+	});
+	img.save(filename)
+}
+
+fn run_render(render: &cli::Render) {
+	if let Some(ref buddhabrot) = render.buddhabrot {
+		let pixels = buddhabrot::render(render.bounds, render.upper_left, render.lower_right,
+										buddhabrot.samples, buddhabrot.seed, render.config.limit,
+										&render.config.palette);
+		write_image(&render.output, &pixels, render.bounds).expect("error writing PNG file");
+		return;
+	}
 
-	// write_image(&args[1], &pixels, bounds)
-	// 	.expect("error writing PNG file");
+	let progress = match render.resume {
+		None => Progress::new(render.bounds, render.upper_left, render.lower_right, &render.config),
+		Some(ref filename) => {
+			match Progress::load(filename, render.bounds, render.upper_left, render.lower_right,
+								 &render.config) {
+				Ok(progress) => progress,
+				Err(message) => {
+					eprintln!("error: {}", message);
+					std::process::exit(1);
+				}
+			}
+		}
+	};
+	let rendering = render_tiles(progress, render.bounds, render.upper_left, render.lower_right,
+								 &render.config, render.checkpoint.as_ref(), num_cpus::get());
+
+	if render.stats {
+		let stats = escape_stats(&rendering.field);
+		println!("interior: {:.2}% of {} pixels", stats.interior_fraction * 100.0, stats.pixels);
+		match stats.exterior {
+			None => println!("no pixels escaped"),
+			Some(exterior) => println!("escape counts: mean {:.2}, median {}, min {}, max {}",
+									   exterior.mean, exterior.median, exterior.min, exterior.max),
+		}
+		println!("samples: {} ({:.2} per pixel)", rendering.samples,
+				 rendering.samples as f64 / stats.pixels as f64);
+	}
+
+	if let Some(ref filename) = render.report {
+		RenderReport::new(render, &rendering).write(filename)
+			.expect("error writing report");
+	}
+
+	let mut pixels = match render.diff {
+		None => rendering.pixels,
+		Some(ref against) => {
+			let other = reuse_field(&rendering.field, &render.config, against)
+				.and_then(|field| colorize(&field, render.bounds, against.mode, against.limit,
+										   &against.palette))
+				.unwrap_or_else(|| {
+					render_image(render.bounds, render.upper_left, render.lower_right, against).pixels
+				});
+			difference(&rendering.pixels, &other)
+		}
+	};
+	if let Some(ref contours) = render.contours {
+		let mask = contour_mask(&rendering.field, render.bounds, &contours.levels);
+		for (pixel, &on) in pixels.iter_mut().zip(&mask) {
+			if on {
+				*pixel = contours.color;
+			}
+		}
+	}
+	match render.alpha {
+		None => write_image(&render.output, &pixels, render.bounds),
+		Some(mode) => {
+			let alpha = alpha_mask(&rendering.field, render.bounds, render.upper_left,
+								   render.lower_right, &render.config, mode);
+			write_rgba_image(&render.output, &pixels, &alpha, render.bounds)
+		}
+	}.expect("error writing PNG file");
+}
+
+fn run_probe(probe: &cli::Probe) {
+	match escape_time(probe.point, probe.limit) {
+		Some(count) => println!("{} escapes after {} iterations", probe.point, count),
+		None => println!("{} did not escape within {} iterations; it is probably in the set",
+						 probe.point, probe.limit),
+	}
+}
+
+fn run_estimate_area(estimate: &cli::EstimateArea) {
+	// The whole set lies within this square.
+	let upper_left = Complex { re: -2.0, im: 1.25 };
+	let lower_right = Complex { re: 0.5, im: -1.25 };
+	let config = RenderConfig { limit: estimate.limit, ..RenderConfig::default() };
+	let bounds = (estimate.resolution, estimate.resolution);
+
+	let rendering = render_image(bounds, upper_left, lower_right, &config);
+	let square = (lower_right.re - upper_left.re) * (upper_left.im - lower_right.im);
+	println!("estimated area: {:.4}", escape_stats(&rendering.field).interior_fraction * square);
+}
+
+/// The name of frame number 'frame' of a zoom written with 'prefix'.
+fn frame_filename(prefix: &str, frame: usize) -> String {
+	format!("{}-{:03}.png", prefix, frame)
+}
+
+fn run_zoom(zoom: &cli::Zoom) {
+	let mut width = zoom.width;
+	let mut frames = Vec::new();
+	for frame in 0..zoom.frames {
+		let (upper_left, lower_right) = viewport(zoom.center, width, zoom.bounds);
+		let rendering = render_image(zoom.bounds, upper_left, lower_right, &zoom.config);
+		if zoom.gif.is_some() {
+			frames.push(rendering.pixels);
+		} else {
+			let filename = frame_filename(&zoom.prefix, frame);
+			write_image(&filename, &rendering.pixels, zoom.bounds)
+				.expect("error writing PNG file");
+			println!("wrote {}", filename);
+		}
+		width /= zoom.factor;
+	}
+
+	if let Some(ref filename) = zoom.gif {
+		let output = std::fs::File::create(filename).expect("error creating GIF file");
+		write_gif(std::io::BufWriter::new(output), &frames, zoom.bounds, zoom.delay)
+			.expect("error writing GIF file");
+		println!("wrote {}", filename);
+	}
+}
+
+fn run_serve(serve: cli::Serve) {
+	let listener = match std::net::TcpListener::bind(&serve.address) {
+		Ok(listener) => listener,
+		Err(error) => {
+			eprintln!("error: can't listen on {}: {}", serve.address, error);
+			std::process::exit(1);
+		}
+	};
+	println!("serving on http://{}/mandelbrot.png", serve.address);
+	server::serve(listener, std::sync::Arc::new(serve)).expect("error accepting connection");
+}
+
+fn main() {
+	let args: Vec<String> = std::env::args().skip(1).collect();
+
+	let command = match cli::parse_args(&args) {
+		Ok(command) => command,
+		Err(message) => {
+			eprintln!("error: {}", message);
+			eprintln!();
+			eprintln!("{}", cli::USAGE);
+			std::process::exit(1);
+		}
+	};
+
+	match command {
+		cli::Command::Render(render) => run_render(&render),
+		cli::Command::Probe(probe) => run_probe(&probe),
+		cli::Command::EstimateArea(estimate) => run_estimate_area(&estimate),
+		cli::Command::Zoom(zoom) => run_zoom(&zoom),
+		cli::Command::Serve(serve) => run_serve(serve),
+	}
 }
@@ -0,0 +1,175 @@
+//! Turning a scalar between zero and one into a color.
+
+use image::Rgb;
+use lerp::Lerp;
+
+use rng::SplitMix64;
+
+/// A sequence of evenly spaced color stops. Sampling at 0.0 gives the first stop, 1.0 gives the
+/// last, and anything in between blends linearly between the two nearest stops.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+	stops: Vec<Rgb<u8>>,
+}
+
+/// Matplotlib's 'viridis' colormap, sampled at nine evenly spaced points.
+const VIRIDIS: [[u8; 3]; 9] = [
+	[0x44, 0x01, 0x54], [0x47, 0x2d, 0x7b], [0x3b, 0x52, 0x8b], [0x2c, 0x72, 0x8e], [0x21, 0x90, 0x8c],
+	[0x27, 0xad, 0x81], [0x5d, 0xc8, 0x63], [0xaa, 0xdc, 0x32], [0xfd, 0xe7, 0x25],
+];
+
+/// Matplotlib's 'magma' colormap, sampled at nine evenly spaced points.
+const MAGMA: [[u8; 3]; 9] = [
+	[0x00, 0x00, 0x04], [0x1d, 0x11, 0x47], [0x51, 0x12, 0x7c], [0x82, 0x26, 0x81], [0xb6, 0x36, 0x79],
+	[0xe6, 0x51, 0x64], [0xfb, 0x88, 0x61], [0xfe, 0xc2, 0x87], [0xfc, 0xfd, 0xbf],
+];
+
+impl Palette {
+	/// A palette with the given stops, from the color for 0.0 to the color for 1.0.
+	///
+	/// Panics if 'stops' is empty.
+	pub fn new(stops: Vec<Rgb<u8>>) -> Palette {
+		assert!(!stops.is_empty(), "a palette needs at least one color");
+		Palette { stops }
+	}
+
+	/// A palette blending straight from 'lower' to 'upper'.
+	pub fn gradient(lower: Rgb<u8>, upper: Rgb<u8>) -> Palette {
+		Palette::new(vec![lower, upper])
+	}
+
+	/// Look up one of the built-in palettes by name:
+	///
+	/// - 'viridis' and 'magma' are the perceptually uniform colormaps from matplotlib;
+	/// - 'red', 'green' and 'blue' put the scalar in that channel alone, leaving the others zero.
+	pub fn named(name: &str) -> Option<Palette> {
+		let table: &[[u8; 3]] = match name {
+			"viridis" => &VIRIDIS,
+			"magma" => &MAGMA,
+			"red" => &[[0, 0, 0], [255, 0, 0]],
+			"green" => &[[0, 0, 0], [0, 255, 0]],
+			"blue" => &[[0, 0, 0], [0, 0, 255]],
+			_ => return None,
+		};
+		Some(Palette::new(table.iter().map(|&data| Rgb { data }).collect()))
+	}
+
+	/// Make up a palette from 'seed': black, followed by six colors whose hues are evenly spaced
+	/// around the color wheel from a random starting hue, each with a random saturation between
+	/// 0.5 and 1 and a random value between 0.6 and 1. The same seed always gives the same
+	/// palette, so a gallery of them can be reproduced from the seeds alone.
+	pub fn random(seed: u64) -> Palette {
+		const HUES: usize = 6;
+
+		let mut rng = SplitMix64::new(seed);
+		let start = rng.next_f32() * 360.0;
+		let mut stops = vec![Rgb { data: [0, 0, 0] }];
+		for i in 0..HUES {
+			let hue = (start + i as f32 * 360.0 / HUES as f32) % 360.0;
+			let saturation = 0.5 + 0.5 * rng.next_f32();
+			let value = 0.6 + 0.4 * rng.next_f32();
+			stops.push(hsv_to_rgb(hue, saturation, value));
+		}
+		Palette::new(stops)
+	}
+
+	pub fn stops(&self) -> &[Rgb<u8>] {
+		&self.stops
+	}
+
+	/// Replace the color for 0.0.
+	pub fn set_first(&mut self, color: Rgb<u8>) {
+		self.stops[0] = color;
+	}
+
+	/// Replace the color for 1.0.
+	pub fn set_last(&mut self, color: Rgb<u8>) {
+		let last = self.stops.len() - 1;
+		self.stops[last] = color;
+	}
+
+	/// Return the color for 'scalar', which is clamped to [0, 1].
+	pub fn sample(&self, scalar: f32) -> Rgb<u8> {
+		if self.stops.len() == 1 {
+			return self.stops[0];
+		}
+
+		let position = scalar.clamp(0.0, 1.0) * (self.stops.len() - 1) as f32;
+		let index = (position as usize).min(self.stops.len() - 2);
+		let (lower, upper) = (self.stops[index], self.stops[index + 1]);
+		let fraction = position - index as f32;
+
+		let mut color = Rgb { data: [0, 0, 0] };
+		for i in 0..3 {
+			color[i] = (lower[i] as f32).lerp(upper[i] as f32, fraction).round() as u8;
+		}
+		color
+	}
+}
+
+/// Convert a color given as a hue in degrees, and a saturation and value in [0, 1], to RGB.
+fn hsv_to_rgb(hue: f32, saturation: f32, value: f32) -> Rgb<u8> {
+	let chroma = value * saturation;
+	let sector = hue / 60.0;
+	let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+	let (r, g, b) = match sector as u32 {
+		0 => (chroma, x, 0.0),
+		1 => (x, chroma, 0.0),
+		2 => (0.0, chroma, x),
+		3 => (0.0, x, chroma),
+		4 => (x, 0.0, chroma),
+		_ => (chroma, 0.0, x),
+	};
+	let m = value - chroma;
+	let channel = |c: f32| ((c + m) * 255.0).round() as u8;
+	Rgb { data: [channel(r), channel(g), channel(b)] }
+}
+
+impl Default for Palette {
+	/// Black for the set and the slowest escapes, up to white for the fastest.
+	fn default() -> Palette {
+		Palette::gradient(Rgb { data: [0, 0, 0] }, Rgb { data: [255, 255, 255] })
+	}
+}
+
+#[test]
+fn test_sample_gradient() {
+	let palette = Palette::gradient(Rgb { data: [0, 100, 200] }, Rgb { data: [200, 100, 0] });
+	assert_eq!(palette.sample(0.0), Rgb { data: [0, 100, 200] });
+	assert_eq!(palette.sample(0.5), Rgb { data: [100, 100, 100] });
+	assert_eq!(palette.sample(1.0), Rgb { data: [200, 100, 0] });
+	assert_eq!(palette.sample(7.0), palette.sample(1.0));
+	assert_eq!(Palette::new(vec![Rgb { data: [1, 2, 3] }]).sample(0.3), Rgb { data: [1, 2, 3] });
+}
+
+#[test]
+fn test_named_palettes() {
+	let viridis = Palette::named("viridis").unwrap();
+	assert_eq!(viridis.sample(0.0), Rgb { data: [68, 1, 84] });
+	assert_eq!(viridis.sample(1.0), Rgb { data: [253, 231, 37] });
+	assert_eq!(viridis.sample(0.5), Rgb { data: [0x21, 0x90, 0x8c] });
+
+	let magma = Palette::named("magma").unwrap();
+	assert_eq!(magma.sample(0.0), Rgb { data: [0, 0, 4] });
+	assert_eq!(magma.sample(1.0), Rgb { data: [252, 253, 191] });
+
+	let green = Palette::named("green").unwrap();
+	assert_eq!(green.sample(0.5), Rgb { data: [0, 128, 0] });
+	assert_eq!(Palette::named("plaid"), None);
+}
+
+#[test]
+fn test_random_palette() {
+	// These are the stops seed 42 has always given; if they change, so do users' galleries.
+	let palette = Palette::random(42);
+	let stops: Vec<[u8; 3]> = palette.stops().iter().map(|color| color.data).collect();
+	assert_eq!(stops, vec![[0, 0, 0], [123, 76, 181], [157, 51, 109], [175, 85, 12],
+						   [112, 188, 19], [33, 174, 96], [52, 136, 205]]);
+	assert_eq!(Palette::random(42), palette);
+	assert!(Palette::random(43) != palette);
+
+	assert_eq!(hsv_to_rgb(0.0, 1.0, 1.0), Rgb { data: [255, 0, 0] });
+	assert_eq!(hsv_to_rgb(120.0, 1.0, 1.0), Rgb { data: [0, 255, 0] });
+	assert_eq!(hsv_to_rgb(240.0, 0.5, 1.0), Rgb { data: [128, 128, 255] });
+	assert_eq!(hsv_to_rgb(300.0, 0.0, 0.2), Rgb { data: [51, 51, 51] });
+}
@@ -0,0 +1,200 @@
+//! Escape times computed with arbitrary-precision floats, for zooms so deep that neighboring
+//! pixels are closer together than 'f64' can tell apart.
+
+use std::convert::TryFrom;
+
+use dashu_float::FBig;
+use dashu_float::round::mode::HalfEven;
+use num::Complex;
+
+use super::ExactCorners;
+
+/// A binary float with as many bits of precision as it was created with.
+type Float = FBig<HalfEven>;
+
+/// Convert 'x' to a 'Float' with 'precision' bits. Every 'f64' converts exactly at 53 bits or
+/// more.
+fn float(x: f64, precision: usize) -> Float {
+	Float::try_from(x).expect("coordinates must be finite").with_precision(precision).value()
+}
+
+/// Convert the decimal 's' to a 'Float' with 'precision' bits, rounding only once, from its
+/// exact value. That is what lets two corners closer together than an 'f64' can record stay
+/// apart.
+fn decimal(s: &str, precision: usize) -> Float {
+	match s.parse::<FBig<HalfEven, 10>>() {
+		Ok(x) => x.with_base_and_precision::<2>(precision).value(),
+		// The few forms that 'f64' reads and 'FBig' doesn't, like a leading '+', go through
+		// 'f64'. Their digits were checked when the corners were parsed.
+		Err(_) => float(s.parse().expect("corners must be numbers"), precision),
+	}
+}
+
+/// Like 'escape_time', but iterating with 'precision' bits rather than the 53 of an 'f64'.
+/// 'c' is given as its real and imaginary parts, each with that precision.
+pub fn escape_time_hp(c: (&Float, &Float), limit: u32, precision: usize) -> Option<u32> {
+	let (c_re, c_im) = c;
+	let two = float(2.0, precision);
+	let four = float(4.0, precision);
+	let mut z_re = float(0.0, precision);
+	let mut z_im = float(0.0, precision);
+	for i in 0..limit {
+		let re = z_re.sqr() - z_im.sqr() + c_re;
+		z_im = &two * &z_re * &z_im + c_im;
+		z_re = re;
+		if z_re.sqr() + z_im.sqr() > four {
+			return Some(i);
+		}
+	}
+	None
+}
+
+/// The corners of a buffer of pixels, converted once to 'precision' bits, for placing each of
+/// its samples between them as 'subpixel_to_point' does, but without an 'f64' rounding
+/// neighboring pixels together.
+pub struct Corners {
+	left: Float,
+	top: Float,
+	width: Float,
+	height: Float,
+	/// The dimensions of the image the corners describe.
+	bounds: (Float, Float),
+	/// The row of that image at which the buffer starts.
+	first_row: f64,
+	precision: usize,
+}
+
+impl Corners {
+	/// The corners of a buffer with dimensions 'bounds'. If 'exact' is given, the corners are
+	/// its own, which are only rounded to 'precision' bits, and the buffer is the band of rows
+	/// it says. Otherwise they are 'upper_left' and 'lower_right', as precise as an 'f64'.
+	pub fn new(bounds: (usize, usize),
+			   upper_left: Complex<f64>,
+			   lower_right: Complex<f64>,
+			   exact: Option<&ExactCorners>,
+			   precision: usize)
+		-> Corners
+	{
+		let (left, top, right, bottom, bounds, first_row) = match exact {
+			Some(exact) => (decimal(&exact.upper_left.0, precision),
+							decimal(&exact.upper_left.1, precision),
+							decimal(&exact.lower_right.0, precision),
+							decimal(&exact.lower_right.1, precision),
+							exact.bounds,
+							exact.first_row),
+			None => (float(upper_left.re, precision),
+					 float(upper_left.im, precision),
+					 float(lower_right.re, precision),
+					 float(lower_right.im, precision),
+					 bounds,
+					 0),
+		};
+		Corners {
+			width: right - &left,
+			height: &top - bottom,
+			left,
+			top,
+			bounds: (float(bounds.0 as f64, precision), float(bounds.1 as f64, precision)),
+			first_row: first_row as f64,
+			precision,
+		}
+	}
+
+	/// The point at 'position' within the buffer, with 'precision' bits.
+	fn point_at(&self, position: (f64, f64)) -> (Float, Float) {
+		let column = float(position.0, self.precision);
+		let row = float(self.first_row + position.1, self.precision);
+		let re = &self.left + &self.width * column / &self.bounds.0;
+		let im = &self.top - &self.height * row / &self.bounds.1;
+		(re, im)
+	}
+
+	/// Like 'subpixel_to_point' followed by 'escape_time_hp', placing the point as 'point_at'
+	/// does.
+	pub fn escape_time(&self, position: (f64, f64), limit: u32) -> Option<u32> {
+		let (re, im) = self.point_at(position);
+		escape_time_hp((&re, &im), limit, self.precision)
+	}
+}
+
+#[cfg(test)]
+use super::{RenderConfig, escape_time, render_image, subpixel_to_point};
+
+#[test]
+fn test_agrees_with_f64() {
+	// A modest zoom near the boundary, where an 'f64' still has bits to spare.
+	let bounds = (24, 16);
+	let upper_left = Complex { re: -0.7455, im: 0.1135 };
+	let lower_right = Complex { re: -0.7435, im: 0.1125 };
+	let corners = Corners::new(bounds, upper_left, lower_right, None, 128);
+	for row in 0..bounds.1 {
+		for column in 0..bounds.0 {
+			let position = (column as f64 + 0.5, row as f64 + 0.5);
+			let point = subpixel_to_point(bounds, position, upper_left, lower_right);
+			assert_eq!(corners.escape_time(position, 200),
+					   escape_time(point, 200),
+					   "disagreement at {}", point);
+		}
+	}
+}
+
+#[cfg(test)]
+fn exact_corners(upper_left: (&str, &str), lower_right: (&str, &str), bounds: (usize, usize))
+	-> ExactCorners
+{
+	ExactCorners {
+		upper_left: (upper_left.0.to_string(), upper_left.1.to_string()),
+		lower_right: (lower_right.0.to_string(), lower_right.1.to_string()),
+		bounds,
+		first_row: 0,
+	}
+}
+
+#[test]
+fn test_corners_closer_than_an_ulp() {
+	// The corners are 1e-22 apart, far less than the 1.4e-17 between neighboring 'f64's here,
+	// so as 'f64's they are the same point.
+	let (left, right) = ("0.1", "0.1000000000000000000001");
+	let (top, bottom) = ("0.2", "0.1999999999999999999999");
+	assert_eq!(left.parse::<f64>(), right.parse::<f64>());
+	assert_eq!(top.parse::<f64>(), bottom.parse::<f64>());
+
+	let bounds = (4, 4);
+	let point = Complex { re: 0.1, im: 0.2 };
+	let mut exact = exact_corners((left, top), (right, bottom), bounds);
+	let at = |exact: &ExactCorners, position| {
+		Corners::new(bounds, point, point, Some(exact), 128).point_at(position)
+	};
+
+	// Every column is a different point, and the image reaches all the way across.
+	let columns: Vec<Float> = (0..5).map(|column| at(&exact, (column as f64, 0.0)).0).collect();
+	assert!(columns.windows(2).all(|pair| pair[0] < pair[1]));
+	assert_eq!(columns[0], decimal(left, 128));
+	let error = (&columns[4] - decimal(right, 128)).to_f64().value();
+	assert!(error.abs() < 1e-35, "the right edge is off by {}", error);
+
+	// Rows too, and a band's rows are counted from its first row within the whole image.
+	let rows: Vec<Float> = (0..5).map(|row| at(&exact, (0.0, row as f64)).1).collect();
+	assert!(rows.windows(2).all(|pair| pair[0] > pair[1]));
+	let whole = at(&exact, (0.5, 2.5));
+	exact.first_row = 2;
+	assert_eq!(at(&exact, (0.5, 0.5)), whole);
+}
+
+#[test]
+fn test_exact_corners_through_tiles() {
+	// Given the decimal text of corners that an 'f64' holds closely enough for this zoom, the
+	// exact path must agree with f64, tile after tile.
+	let bounds = (12, 40);
+	let upper_left = Complex { re: -0.7455, im: 0.1135 };
+	let lower_right = Complex { re: -0.7435, im: 0.1115 };
+	let plain = RenderConfig { limit: 200, ..RenderConfig::default() };
+	let exact = RenderConfig {
+		precision: 128,
+		exact_corners: Some(Box::new(exact_corners(("-0.7455", "0.1135"), ("-0.7435", "0.1115"),
+												   bounds))),
+		..plain.clone()
+	};
+	assert_eq!(render_image(bounds, upper_left, lower_right, &exact).field,
+			   render_image(bounds, upper_left, lower_right, &plain).field);
+}
@@ -0,0 +1,107 @@
+//! A machine-readable record of a render's settings and timings, so that runs can be compared
+//! with one another as the renderer changes.
+
+use std::fs::File;
+use std::io;
+
+use serde_json;
+
+use super::{ColorMode, Rendering, escape_stats};
+use cli;
+#[cfg(test)]
+use super::{RenderConfig, render_image};
+#[cfg(test)]
+use num::Complex;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct RenderReport {
+	pub width: usize,
+	pub height: usize,
+	/// The corners of the region rendered, as '(re, im)' pairs.
+	pub upper_left: (f64, f64),
+	pub lower_right: (f64, f64),
+	pub mode: ColorMode,
+	/// The palette's color stops, as '[r, g, b]' triples.
+	pub palette: Vec<[u8; 3]>,
+	pub limit: u32,
+	pub samples: usize,
+	/// The threshold for adaptive supersampling, if it was used.
+	pub adaptive: Option<u8>,
+	/// How many samples were actually taken, which with adaptive supersampling may be far
+	/// fewer than 'samples' for every pixel.
+	pub samples_taken: usize,
+	/// Bits of precision the iteration used.
+	pub precision: usize,
+	/// The formula iterated, if not the usual 'z^2 + c'.
+	pub formula: Option<String>,
+	pub threads: usize,
+	pub pixels: usize,
+	/// Wall-clock time for the whole render.
+	pub total_seconds: f64,
+	/// Wall-clock time for each tile rendered, top to bottom.
+	pub tile_seconds: Vec<f64>,
+	/// The mean escape count of the pixels outside the set, or 'None' if there were none.
+	pub mean_escape_iterations: Option<f64>,
+}
+
+impl RenderReport {
+	pub fn new(render: &cli::Render, rendering: &Rendering) -> RenderReport {
+		RenderReport {
+			width: render.bounds.0,
+			height: render.bounds.1,
+			upper_left: (render.upper_left.re, render.upper_left.im),
+			lower_right: (render.lower_right.re, render.lower_right.im),
+			mode: render.config.mode,
+			palette: render.config.palette.stops().iter().map(|color| color.data).collect(),
+			limit: render.config.limit,
+			samples: render.config.samples,
+			adaptive: render.config.adaptive,
+			samples_taken: rendering.samples,
+			precision: render.config.precision,
+			formula: render.config.formula.as_ref().map(|formula| formula.source().to_string()),
+			threads: rendering.threads,
+			pixels: rendering.pixels.len(),
+			total_seconds: rendering.total_time.as_secs_f64(),
+			tile_seconds: rendering.tile_times.iter().map(|time| time.as_secs_f64()).collect(),
+			mean_escape_iterations: escape_stats(&rendering.field).exterior.map(|exterior| exterior.mean),
+		}
+	}
+
+	/// Write the report to the file named 'filename' as JSON.
+	pub fn write(&self, filename: &str) -> io::Result<()> {
+		let output = File::create(filename)?;
+		serde_json::to_writer_pretty(output, self)?;
+		Ok(())
+	}
+}
+
+#[test]
+fn test_report_round_trip() {
+	let render = cli::Render {
+		output: "unused.png".to_string(),
+		bounds: (64, 48),
+		upper_left: Complex { re: -2.0, im: 1.2 },
+		lower_right: Complex { re: 1.0, im: -1.2 },
+		config: RenderConfig { limit: 200, ..RenderConfig::default() },
+		stats: false,
+		alpha: None,
+		report: None,
+		diff: None,
+		checkpoint: None,
+		resume: None,
+		contours: None,
+		buddhabrot: None,
+	};
+	let rendering = render_image(render.bounds, render.upper_left, render.lower_right, &render.config);
+	let report = RenderReport::new(&render, &rendering);
+
+	assert!(report.total_seconds > 0.0);
+	assert_eq!(report.pixels, 64 * 48);
+	assert_eq!(report.palette, vec![[0, 0, 0], [255, 255, 255]]);
+	assert_eq!(report.tile_seconds.len(), rendering.tile_times.len());
+	assert!(report.mean_escape_iterations.unwrap() > 0.0);
+
+	let json = serde_json::to_string(&report).unwrap();
+	let parsed: RenderReport = serde_json::from_str(&json).unwrap();
+	assert_eq!(parsed, report);
+}
@@ -0,0 +1,323 @@
+//! Serving renders over HTTP, so that a browser can ask for any view of the set by URL:
+//!
+//!     GET /mandelbrot.png?center=-0.75,0.1&zoom=4&size=400x300
+//!
+//! 'center' is the point in the middle of the image, 'zoom' how many times narrower than the
+//! usual three units the view is, and 'size' the image's dimensions in pixels. Each may be
+//! left out, giving the whole set at 800x600.
+//!
+//! A fixed number of workers answer requests, each rendering with its share of the CPUs, so
+//! however many clients connect at once, the machine runs about one rendering thread per CPU.
+
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, TrySendError};
+use std::thread;
+use std::time::Duration;
+
+use image::ColorType;
+use image::png::PNGEncoder;
+use num::Complex;
+
+use super::{render_image_on, viewport};
+use cli::{self, parse_bounds, parse_point, parse_positive};
+
+/// The width on the complex plane of an image at zoom 1, which shows the whole set.
+const FULL_WIDTH: f64 = 3.0;
+
+/// How many requests are answered at once.
+const WORKERS: usize = 4;
+
+/// How many connections may wait for a worker. Beyond that, new ones are turned away at once.
+const BACKLOG: usize = 16;
+
+/// How long to wait for a client to send its request before giving up on it.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to wait for a client to take each part of the answer before giving up on it.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The most a request line and its headers may take up together.
+const MAX_HEAD: u64 = 8192;
+
+/// Answer requests on 'listener' forever. The listener only hands connections to the workers,
+/// so that a slow render doesn't keep it from taking the next one. Trouble with one connection,
+/// even a panic while answering it, is logged and goes no further than that connection.
+pub fn serve(listener: TcpListener, serve: Arc<cli::Serve>) -> io::Result<()> {
+	let threads = (num_cpus::get() / WORKERS).max(1);
+	let (sender, receiver) = mpsc::sync_channel::<TcpStream>(BACKLOG);
+	let receiver = Arc::new(Mutex::new(receiver));
+	for _ in 0..WORKERS {
+		let receiver = receiver.clone();
+		let serve = serve.clone();
+		thread::spawn(move || {
+			loop {
+				let next = receiver.lock().unwrap().recv();
+				let stream = match next {
+					Ok(stream) => stream,
+					Err(_) => break,
+				};
+				answer(stream, &serve, threads);
+			}
+		});
+	}
+
+	for stream in listener.incoming() {
+		let stream = match stream {
+			Ok(stream) => stream,
+			Err(error) => {
+				eprintln!("error accepting connection: {}", error);
+				continue;
+			}
+		};
+		let timeouts = stream.set_read_timeout(Some(READ_TIMEOUT))
+			.and_then(|()| stream.set_write_timeout(Some(WRITE_TIMEOUT)));
+		if let Err(error) = timeouts {
+			eprintln!("error setting up connection: {}", error);
+			continue;
+		}
+		if let Err(TrySendError::Full(mut stream)) = sender.try_send(stream) {
+			if let Err(error) = error(&mut stream, "503 Service Unavailable", "too busy; try again later") {
+				eprintln!("error turning away request: {}", error);
+			}
+		}
+	}
+	Ok(())
+}
+
+/// Answer the request on 'stream' with 'respond', logging any error. Should the answer panic,
+/// the client is told so with a 500, and the worker lives on to take the next request.
+fn answer(stream: TcpStream, serve: &cli::Serve, threads: usize) {
+	let fallback = stream.try_clone();
+	match panic::catch_unwind(AssertUnwindSafe(|| respond(stream, serve, threads))) {
+		Ok(Ok(())) => {}
+		Ok(Err(error)) => eprintln!("error answering request: {}", error),
+		Err(_) => {
+			let sent = fallback.and_then(|mut stream| {
+				error(&mut stream, "500 Internal Server Error", "the render failed")
+			});
+			if let Err(error) = sent {
+				eprintln!("error reporting a failed render: {}", error);
+			}
+		}
+	}
+}
+
+/// What a request for '/mandelbrot.png' asked to see.
+#[derive(Debug, PartialEq)]
+struct View {
+	center: Complex<f64>,
+	zoom: f64,
+	bounds: (usize, usize),
+}
+
+impl View {
+	/// The upper-left and lower-right corners of the view on the complex plane.
+	fn corners(&self) -> (Complex<f64>, Complex<f64>) {
+		viewport(self.center, FULL_WIDTH / self.zoom, self.bounds)
+	}
+}
+
+/// Parse the query string of a request, like '"center=-0.75,0.1&size=400x300"'.
+fn parse_query(query: &str) -> Result<View, String> {
+	let mut view = View { center: Complex { re: -0.5, im: 0.0 }, zoom: 1.0, bounds: (800, 600) };
+	for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+		let (name, value) = match pair.find('=') {
+			Some(equals) => (&pair[..equals], percent_decode(&pair[equals + 1..])),
+			None => return Err(format!("parameter '{}' needs a value", pair)),
+		};
+		let error = || format!("error parsing {} value '{}'", name, value);
+		match name {
+			"center" => view.center = parse_point(&value).ok_or_else(error)?,
+			"zoom" => {
+				view.zoom = parse_positive(&value).filter(|zoom: &f64| zoom.is_finite()).ok_or_else(error)?
+			}
+			"size" => view.bounds = parse_bounds(&value).ok_or_else(error)?,
+			_ => return Err(format!("unknown parameter '{}'", name)),
+		}
+	}
+	// A small enough zoom, or a center far enough out, puts the edges of the view beyond what
+	// an 'f64' can hold.
+	let (upper_left, lower_right) = view.corners();
+	if ![upper_left.re, upper_left.im, lower_right.re, lower_right.im].iter().all(|x| x.is_finite()) {
+		return Err("the view reaches too far out on the plane".to_string());
+	}
+	Ok(view)
+}
+
+/// Undo the '%XX' escapes that browsers put in URLs, such as '%2C' for a comma. Escapes that
+/// don't make sense are left as they are.
+fn percent_decode(s: &str) -> String {
+	let bytes = s.as_bytes();
+	let mut decoded = Vec::with_capacity(bytes.len());
+	let mut i = 0;
+	while i < bytes.len() {
+		let escaped = bytes.get(i + 1..i + 3)
+			.filter(|_| bytes[i] == b'%')
+			.and_then(|hex| std::str::from_utf8(hex).ok())
+			.and_then(|hex| u8::from_str_radix(hex, 16).ok());
+		match escaped {
+			Some(byte) => {
+				decoded.push(byte);
+				i += 3;
+			}
+			None => {
+				decoded.push(bytes[i]);
+				i += 1;
+			}
+		}
+	}
+	String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Read one request from 'stream' and answer it, either with a PNG rendered with 'threads'
+/// threads or with an error and a line of text saying what was wrong with the request.
+fn respond(stream: TcpStream, serve: &cli::Serve, threads: usize) -> io::Result<()> {
+	let mut reader = BufReader::new(stream.try_clone()?.take(MAX_HEAD));
+	let mut request_line = String::new();
+	reader.read_line(&mut request_line)?;
+
+	// Skip the headers; nothing in them changes the answer.
+	let mut header = String::new();
+	let mut ended = false;
+	while !ended && reader.read_line(&mut header)? > 0 {
+		ended = header.trim_end() == "";
+		header.clear();
+	}
+
+	let mut output = BufWriter::new(stream);
+	if !ended && reader.get_ref().limit() == 0 {
+		let message = format!("the request line and headers can take at most {} bytes", MAX_HEAD);
+		return error(&mut output, "431 Request Header Fields Too Large", &message);
+	}
+	let mut words = request_line.split_whitespace();
+	let (method, target) = match (words.next(), words.next()) {
+		(Some(method), Some(target)) => (method, target),
+		_ => return error(&mut output, "400 Bad Request", "malformed request line"),
+	};
+	let (path, query) = match target.find('?') {
+		Some(question) => (&target[..question], &target[question + 1..]),
+		None => (target, ""),
+	};
+	if path != "/mandelbrot.png" {
+		return error(&mut output, "404 Not Found", "the only image here is /mandelbrot.png");
+	}
+	if method != "GET" {
+		return error(&mut output, "405 Method Not Allowed", "only GET is supported");
+	}
+
+	let view = match parse_query(query) {
+		Ok(view) => view,
+		Err(message) => return error(&mut output, "400 Bad Request", &message),
+	};
+	let pixels = view.bounds.0.checked_mul(view.bounds.1);
+	if pixels.is_none_or(|pixels| pixels > serve.max_pixels) {
+		let message = format!("images can have at most {} pixels", serve.max_pixels);
+		return error(&mut output, "400 Bad Request", &message);
+	}
+
+	let (upper_left, lower_right) = view.corners();
+	let rendering = render_image_on(threads, view.bounds, upper_left, lower_right, &serve.config);
+	let data: Vec<u8> = rendering.pixels.iter().flat_map(|pixel| pixel.data.to_vec()).collect();
+
+	// With the connection closing afterwards, the PNG can go out as it's encoded, without
+	// working out its length first.
+	write!(output, "HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nConnection: close\r\n\r\n")?;
+	PNGEncoder::new(&mut output)
+		.encode(&data, view.bounds.0 as u32, view.bounds.1 as u32, ColorType::RGB(8))?;
+	output.flush()
+}
+
+/// Answer with 'status' and 'message' as plain text.
+fn error<W: Write>(output: &mut W, status: &str, message: &str) -> io::Result<()> {
+	write!(output,
+		   "HTTP/1.1 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}\n",
+		   status, message.len() + 1, message)?;
+	output.flush()
+}
+
+#[cfg(test)]
+use super::RenderConfig;
+
+#[test]
+fn test_parse_query() {
+	assert_eq!(parse_query("center=-0.75%2C0.1&zoom=4&size=40x30"),
+			   Ok(View { center: Complex { re: -0.75, im: 0.1 }, zoom: 4.0, bounds: (40, 30) }));
+	assert_eq!(parse_query("").map(|view| view.bounds), Ok((800, 600)));
+	assert!(parse_query("zoom=0").is_err());
+	assert!(parse_query("size=40").is_err());
+	assert!(parse_query("colour=red").is_err());
+	assert!(parse_query("center=inf,0").is_err());
+	assert!(parse_query("center=0,NaN").is_err());
+	assert!(parse_query("zoom=inf").is_err());
+	assert!(parse_query("zoom=1e-308").is_err());
+	assert!(parse_query("center=1.7e308,0&zoom=3e-308").is_err());
+	assert!(parse_query("center=1.7e308,0").is_ok());
+	assert_eq!(percent_decode("a%2cb%zz%2"), "a,b%zz%2");
+}
+
+/// Send 'request' to the server at 'address', and return the head of the response and its
+/// body.
+#[cfg(test)]
+fn exchange(address: std::net::SocketAddr, request: &str) -> (String, Vec<u8>) {
+	let mut stream = TcpStream::connect(address).unwrap();
+	stream.write_all(request.as_bytes()).unwrap();
+	let mut response = Vec::new();
+	stream.read_to_end(&mut response).unwrap();
+	let end = response.windows(4).position(|window| window == b"\r\n\r\n").unwrap();
+	let head = String::from_utf8(response[..end].to_vec()).unwrap();
+	(head, response[end + 4..].to_vec())
+}
+
+#[test]
+fn test_serve_png() {
+	let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+	let address = listener.local_addr().unwrap();
+	let settings = cli::Serve {
+		address: address.to_string(),
+		max_pixels: 10_000,
+		config: RenderConfig { limit: 100, ..RenderConfig::default() },
+	};
+	thread::spawn(move || serve(listener, Arc::new(settings)));
+
+	let get = |target: &str| {
+		exchange(address, &format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", target))
+	};
+
+	let (head, body) = get("/mandelbrot.png?center=-0.75,0.1&zoom=2&size=40x30");
+	assert!(head.starts_with("HTTP/1.1 200 OK\r\n"), "{}", head);
+	assert!(head.contains("Content-Type: image/png"));
+	let image = image::load_from_memory(&body).unwrap().to_rgb();
+	assert_eq!(image.dimensions(), (40, 30));
+
+	let (head, body) = get("/mandelbrot.png?size=200x200");
+	assert!(head.starts_with("HTTP/1.1 400 Bad Request\r\n"), "{}", head);
+	assert_eq!(body, b"images can have at most 10000 pixels\n");
+
+	assert!(get("/favicon.ico").0.starts_with("HTTP/1.1 404"));
+
+	// Views off the edge of the plane are turned away, and no worker is lost to them.
+	for _ in 0..=WORKERS {
+		assert!(get("/mandelbrot.png?center=inf,0").0.starts_with("HTTP/1.1 400"));
+	}
+	assert!(get("/mandelbrot.png?size=20x15").0.starts_with("HTTP/1.1 200 OK\r\n"));
+
+	// However long the headers go on, only so much of them is read. This request is just
+	// that long, with no end to its headers in sight.
+	let start = "GET /mandelbrot.png HTTP/1.1\r\nX-Padding: ";
+	let padding = "x".repeat(MAX_HEAD as usize - start.len() - 2);
+	let (head, _) = exchange(address, &format!("{}{}\r\n", start, padding));
+	assert!(head.starts_with("HTTP/1.1 431"), "{}", head);
+
+	// More requests at once than there are workers all get answered.
+	let answers: Vec<_> = (0..2 * WORKERS).map(|_| {
+		thread::spawn(move || {
+			exchange(address, "GET /mandelbrot.png?size=20x15 HTTP/1.1\r\n\r\n").0
+		})
+	}).collect();
+	for answer in answers {
+		assert!(answer.join().unwrap().starts_with("HTTP/1.1 200 OK\r\n"));
+	}
+}
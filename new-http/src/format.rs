@@ -0,0 +1,145 @@
+//! Ways of writing out the numbers FizzBuzz has nothing to say about.
+
+/// Turns a plain number into the text printed for it.
+pub trait NumberFormatter {
+	fn format(&self, n: u32) -> String;
+}
+
+/// Ordinary base-ten digits.
+pub struct Decimal;
+
+impl NumberFormatter for Decimal {
+	fn format(&self, n: u32) -> String {
+		n.to_string()
+	}
+}
+
+/// Lowercase hexadecimal, without a '0x' prefix.
+pub struct Hex;
+
+impl NumberFormatter for Hex {
+	fn format(&self, n: u32) -> String {
+		format!("{:x}", n)
+	}
+}
+
+/// Base two, without a '0b' prefix.
+pub struct Binary;
+
+impl NumberFormatter for Binary {
+	fn format(&self, n: u32) -> String {
+		format!("{:b}", n)
+	}
+}
+
+/// Roman numerals, like '"XIV"'. There is no numeral for zero, so it is written 'N', for
+/// 'nulla', and thousands beyond the third are just more 'M's.
+pub struct Roman;
+
+impl NumberFormatter for Roman {
+	fn format(&self, mut n: u32) -> String {
+		const NUMERALS: [(u32, &str); 13] = [
+			(1000, "M"), (900, "CM"), (500, "D"), (400, "CD"), (100, "C"), (90, "XC"), (50, "L"),
+			(40, "XL"), (10, "X"), (9, "IX"), (5, "V"), (4, "IV"), (1, "I"),
+		];
+
+		if n == 0 {
+			return "N".to_string();
+		}
+		let mut roman = String::new();
+		for &(value, numeral) in &NUMERALS {
+			while n >= value {
+				roman.push_str(numeral);
+				n -= value;
+			}
+		}
+		roman
+	}
+}
+
+/// English words, like '"one hundred forty-two"'.
+pub struct English;
+
+const ONES: [&str; 20] = [
+	"zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten",
+	"eleven", "twelve", "thirteen", "fourteen", "fifteen", "sixteen", "seventeen", "eighteen",
+	"nineteen",
+];
+
+const TENS: [&str; 10] = [
+	"", "", "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety",
+];
+
+/// Spell out 'n', which must be between 1 and 999.
+fn spell_hundreds(n: u32) -> String {
+	let (hundreds, rest) = (n / 100, n % 100);
+	let mut words = Vec::new();
+	if hundreds > 0 {
+		words.push(format!("{} hundred", ONES[hundreds as usize]));
+	}
+	match rest {
+		0 => {}
+		1..=19 => words.push(ONES[rest as usize].to_string()),
+		_ if rest % 10 == 0 => words.push(TENS[(rest / 10) as usize].to_string()),
+		_ => words.push(format!("{}-{}", TENS[(rest / 10) as usize], ONES[(rest % 10) as usize])),
+	}
+	words.join(" ")
+}
+
+impl NumberFormatter for English {
+	fn format(&self, n: u32) -> String {
+		const SCALES: [(u32, &str); 4] = [
+			(1_000_000_000, " billion"), (1_000_000, " million"), (1000, " thousand"), (1, ""),
+		];
+
+		if n == 0 {
+			return ONES[0].to_string();
+		}
+		SCALES.iter()
+			.map(|&(scale, name)| (n / scale % 1000, name))
+			.filter(|&(group, _)| group > 0)
+			.map(|(group, name)| spell_hundreds(group) + name)
+			.collect::<Vec<String>>()
+			.join(" ")
+	}
+}
+
+/// Look up a formatter by the name given on the command line.
+pub fn named(name: &str) -> Option<Box<dyn NumberFormatter>> {
+	match name {
+		"decimal" => Some(Box::new(Decimal)),
+		"hex" => Some(Box::new(Hex)),
+		"binary" => Some(Box::new(Binary)),
+		"roman" => Some(Box::new(Roman)),
+		"english" => Some(Box::new(English)),
+		_ => None,
+	}
+}
+
+#[test]
+fn test_roman() {
+	let numerals: Vec<String> = [1, 4, 9, 14, 40, 90, 400, 1994, 3999, 0].iter()
+		.map(|&n| Roman.format(n))
+		.collect();
+	assert_eq!(numerals, vec!["I", "IV", "IX", "XIV", "XL", "XC", "CD", "MCMXCIV", "MMMCMXCIX", "N"]);
+	assert_eq!(Roman.format(5000), "MMMMM");
+}
+
+#[test]
+fn test_english() {
+	assert_eq!(English.format(0), "zero");
+	assert_eq!(English.format(13), "thirteen");
+	assert_eq!(English.format(40), "forty");
+	assert_eq!(English.format(142), "one hundred forty-two");
+	assert_eq!(English.format(1_000_001), "one million one");
+	assert_eq!(English.format(u32::MAX),
+			   "four billion two hundred ninety-four million nine hundred sixty-seven thousand \
+				two hundred ninety-five");
+}
+
+#[test]
+fn test_named() {
+	assert_eq!(named("binary").unwrap().format(6), "110");
+	assert_eq!(named("decimal").unwrap().format(6), "6");
+	assert!(named("octal").is_none());
+}
@@ -1,20 +1,260 @@
-use std::iter::{once, repeat};
+mod format;
+mod table;
+
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use format::NumberFormatter;
+use table::TableFormat;
+
+/// What FizzBuzz says about a number.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Label {
+	Number,
+	Fizz,
+	Buzz,
+	FizzBuzz,
+}
+
+impl Label {
+	/// The word printed for this label, or 'None' for a plain number.
+	fn word(self) -> Option<&'static str> {
+		match self {
+			Label::Number => None,
+			Label::Fizz => Some("fizz"),
+			Label::Buzz => Some("buzz"),
+			Label::FizzBuzz => Some("fizzbuzz"),
+		}
+	}
+}
+
+fn classify(n: u64) -> Label {
+	match (n % 3, n % 5) {
+		(0, 0) => Label::FizzBuzz,
+		(0, _) => Label::Fizz,
+		(_, 0) => Label::Buzz,
+		_ => Label::Number,
+	}
+}
+
+/// Pair every number in 'range' with its label.
+fn labeled(range: RangeInclusive<u32>) -> impl Iterator<Item = (u32, Label)> {
+	range.map(|i| (i, classify(u64::from(i))))
+}
+
+/// Format one line of the classic output, like '"4:"' or '"5: buzz"'.
+fn line(i: u32, label: Label) -> String {
+	match label.word() {
+		None => format!("{}:", i),
+		Some(word) => format!("{}: {}", i, word),
+	}
+}
+
+/// The classic output over 'range', with every number zero-padded to the width of the largest,
+/// so that 1..=100 runs from '"001:"' to '"100: buzz"' and the lines sort as they count.
+fn zero_padded_lines(range: RangeInclusive<u32>) -> impl Iterator<Item = String> {
+	let width = range.end().to_string().len();
+	labeled(range).map(move |(i, label)| match label.word() {
+		None => format!("{:0width$}:", i, width = width),
+		Some(word) => format!("{:0width$}: {}", i, word, width = width),
+	})
+}
+
+/// Like 'line', but write out plain numbers with 'formatter' instead of leaving them blank,
+/// so that 11 in hex is '"11: b"'. Numbers that get a word keep it.
+fn formatted_line(i: u32, label: Label, formatter: &dyn NumberFormatter) -> String {
+	match label.word() {
+		None => format!("{}: {}", i, formatter.format(i)),
+		Some(word) => format!("{}: {}", i, word),
+	}
+}
+
+/// Only the words from 'range', one per matching number, with plain numbers left out
+/// altogether. A range in which nothing matches yields nothing.
+fn words_only(range: RangeInclusive<u32>) -> impl Iterator<Item = &'static str> {
+	labeled(range).filter_map(|(_, label)| label.word())
+}
+
+/// Count the lines of the classic output over 'range' by length, mapping each length to how
+/// many lines have it. An empty range gives an empty map.
+fn label_length_histogram(range: RangeInclusive<u32>) -> BTreeMap<usize, usize> {
+	let mut histogram = BTreeMap::new();
+	for (i, label) in labeled(range) {
+		*histogram.entry(line(i, label).len()).or_insert(0) += 1;
+	}
+	histogram
+}
+
+/// The Fibonacci numbers 1, 1, 2, 3, 5, ..., ending with the largest that fits in a 'u64', which
+/// is the 93rd.
+struct Fibonacci {
+	current: Option<u64>,
+	next: Option<u64>,
+}
+
+fn fibonacci() -> Fibonacci {
+	Fibonacci { current: Some(1), next: Some(1) }
+}
+
+impl Iterator for Fibonacci {
+	type Item = u64;
+
+	fn next(&mut self) -> Option<u64> {
+		let current = self.current?;
+		self.current = self.next;
+		self.next = self.next.and_then(|next| next.checked_add(current));
+		Some(current)
+	}
+}
+
+/// FizzBuzz over the first 'count' Fibonacci numbers instead of the integers, one line per
+/// number like '"4: 3: fizz"', giving its position in the sequence and then its value.
+///
+/// There are only 93 Fibonacci numbers that fit in a 'u64', so asking for more than that still
+/// yields just 93 lines.
+fn fizzbuzz_fib(count: usize) -> impl Iterator<Item = String> {
+	fibonacci().take(count).zip(1..).map(|(n, index)| {
+		match classify(n).word() {
+			None => format!("{}: {}:", index, n),
+			Some(word) => format!("{}: {}: {}", index, n, word),
+		}
+	})
+}
+
+fn usage() -> ! {
+	eprintln!("Usage: new-http [--words-only | --zero-pad | --fib COUNT | \
+			   --format decimal|hex|binary|roman|english | --table markdown|html | --length-histogram]");
+	std::process::exit(1);
+}
 
 fn main() {
-	let fizzes = repeat("").take(2).chain(once("fizz")).cycle();
-	let buzzes = repeat("").take(4).chain(once("buzz")).cycle();
-	let fizz_buzzes = fizzes.zip(buzzes);
+	let mut words = false;
+	let mut zero_pad = false;
+	let mut fib = None;
+	let mut formatter = None;
+	let mut table = None;
+	let mut histogram = false;
+	let mut args = std::env::args().skip(1);
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--words-only" => words = true,
+			"--zero-pad" => zero_pad = true,
+			"--fib" => match args.next().and_then(|count| usize::from_str(&count).ok()) {
+				Some(count) => fib = Some(count),
+				None => usage(),
+			},
+			"--format" => match args.next().and_then(|name| format::named(&name)) {
+				Some(named) => formatter = Some(named),
+				None => usage(),
+			},
+			"--table" => match args.next().and_then(|name| TableFormat::named(&name)) {
+				Some(format) => table = Some(format),
+				None => usage(),
+			},
+			"--length-histogram" => histogram = true,
+			_ => usage(),
+		}
+	}
+
+	// Each mode replaces the classic output, so asking for two would leave one ignored.
+	let modes = [words, zero_pad, fib.is_some(), formatter.is_some(), table.is_some(), histogram];
+	if modes.iter().filter(|&&given| given).count() > 1 {
+		eprintln!("error: choose at most one output mode");
+		usage();
+	}
 
-	let fizz_buzz = (1..101).zip(fizz_buzzes)
-		.map(|tup| {
-			match tup {
-				(i, ("", "")) => format!("{}:", i),
-				(i, (fizz, buzz)) => format!("{}: {}{}", i, fizz, buzz),
-			}
-		});
+	if histogram {
+		for (length, count) in label_length_histogram(1..=100) {
+			println!("{}: {}", length, count);
+		}
+	} else if let Some(format) = table {
+		print!("{}", table::fizzbuzz_table(1..=100, format));
+	} else if let Some(count) = fib {
+		for line in fizzbuzz_fib(count) {
+			println!("{}", line);
+		}
+	} else if let Some(formatter) = formatter {
+		for (i, label) in labeled(1..=100) {
+			println!("{}", formatted_line(i, label, &*formatter));
+		}
+	} else if zero_pad {
+		for line in zero_padded_lines(1..=100) {
+			println!("{}", line);
+		}
+	} else if words {
+		for word in words_only(1..=100) {
+			println!("{}", word);
+		}
+	} else {
+		for (i, label) in labeled(1..=100) {
+			println!("{}", line(i, label));
+		}
+	}
+}
+
+#[test]
+fn test_lines() {
+	let lines: Vec<String> = labeled(1..=5).map(|(i, label)| line(i, label)).collect();
+	assert_eq!(lines, vec!["1:", "2:", "3: fizz", "4:", "5: buzz"]);
+	assert_eq!(line(15, classify(15)), "15: fizzbuzz");
+}
 
-	for fb in fizz_buzz {
-		println!("{}", fb);
+#[test]
+fn test_zero_padded_lines() {
+	let lines: Vec<String> = zero_padded_lines(1..=100).collect();
+	assert_eq!(lines.len(), 100);
+	assert_eq!(lines[0], "001:");
+	assert_eq!(lines[14], "015: fizzbuzz");
+	assert_eq!(lines[99], "100: buzz");
+	for (line, i) in lines.iter().zip(1..) {
+		if classify(i).word().is_none() {
+			assert_eq!(*line, format!("{:03}:", i));
+		}
 	}
 
+	let mut sorted = lines.clone();
+	sorted.sort();
+	assert_eq!(sorted, lines);
+	assert_eq!(zero_padded_lines(1..=9).next(), Some("1:".to_string()));
+}
+
+#[test]
+fn test_formatted_lines() {
+	let lines: Vec<String> = labeled(9..=14)
+		.map(|(i, label)| formatted_line(i, label, &format::Hex))
+		.collect();
+	assert_eq!(lines, vec!["9: fizz", "10: buzz", "11: b", "12: fizz", "13: d", "14: e"]);
+	assert_eq!(formatted_line(4, classify(4), &format::Roman), "4: IV");
+	assert_eq!(formatted_line(4, classify(4), &format::Decimal), "4: 4");
+}
+
+#[test]
+fn test_words_only() {
+	let words: Vec<&str> = words_only(1..=15).collect();
+	assert_eq!(words, vec!["fizz", "buzz", "fizz", "fizz", "buzz", "fizz", "fizzbuzz"]);
+	assert_eq!(words_only(1..=2).count(), 0);
+}
+
+#[test]
+fn test_label_length_histogram() {
+	// "1:" to "9: fizz" are two or seven characters long, and "10: buzz" onwards one more.
+	let histogram = label_length_histogram(1..=20);
+	assert_eq!(histogram[&2], 5);
+	assert_eq!(histogram[&3], 6);
+	assert_eq!(histogram[&7], 4);
+	assert_eq!(histogram[&12], 1);
+	assert_eq!(histogram.values().sum::<usize>(), 20);
+	assert!(label_length_histogram(RangeInclusive::new(1, 0)).is_empty());
+}
+
+#[test]
+fn test_fizzbuzz_fib() {
+	let lines: Vec<String> = fizzbuzz_fib(10).collect();
+	assert_eq!(lines, vec!["1: 1:", "2: 1:", "3: 2:", "4: 3: fizz", "5: 5: buzz", "6: 8:", "7: 13:",
+						   "8: 21: fizz", "9: 34:", "10: 55: buzz"]);
+
+	assert_eq!(fibonacci().count(), 93);
+	assert_eq!(fibonacci().last(), Some(12_200_160_415_121_876_738));
+	assert_eq!(fizzbuzz_fib(1000).count(), 93);
 }
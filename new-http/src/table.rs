@@ -0,0 +1,133 @@
+//! FizzBuzz as a table, for pasting into documents.
+
+use std::ops::RangeInclusive;
+
+use super::labeled;
+
+/// The markup to write a table in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TableFormat {
+	Markdown,
+	Html,
+}
+
+impl TableFormat {
+	pub fn named(name: &str) -> Option<TableFormat> {
+		match name {
+			"markdown" => Some(TableFormat::Markdown),
+			"html" => Some(TableFormat::Html),
+			_ => None,
+		}
+	}
+}
+
+/// A table with an 'n' column and a 'label' column, one row per number in 'range'. Plain
+/// numbers get an empty label, as in the classic output.
+pub fn fizzbuzz_table(range: RangeInclusive<u32>, format: TableFormat) -> String {
+	let rows = labeled(range).map(|(i, label)| (i.to_string(), label.word().unwrap_or("")));
+	match format {
+		TableFormat::Markdown => {
+			let mut table = String::from("| n | label |\n| --- | --- |\n");
+			for (n, word) in rows {
+				table.push_str(&format!("| {} | {} |\n", n, word));
+			}
+			table
+		}
+		TableFormat::Html => {
+			let mut html = HtmlBuilder::new();
+			html.open("table");
+			html.open("tr");
+			html.element("th", "n");
+			html.element("th", "label");
+			html.close("tr");
+			for (n, word) in rows {
+				html.open("tr");
+				html.element("td", &n);
+				html.element("td", word);
+				html.close("tr");
+			}
+			html.close("table");
+			html.finish()
+		}
+	}
+}
+
+/// Builds HTML one tag at a time, escaping all text, with each tag on a line of its own and
+/// indented by its depth.
+struct HtmlBuilder {
+	html: String,
+	depth: usize,
+}
+
+impl HtmlBuilder {
+	fn new() -> HtmlBuilder {
+		HtmlBuilder { html: String::new(), depth: 0 }
+	}
+
+	fn indent(&mut self) {
+		for _ in 0..self.depth {
+			self.html.push_str("  ");
+		}
+	}
+
+	fn open(&mut self, tag: &str) {
+		self.indent();
+		self.html.push_str(&format!("<{}>\n", tag));
+		self.depth += 1;
+	}
+
+	fn close(&mut self, tag: &str) {
+		self.depth -= 1;
+		self.indent();
+		self.html.push_str(&format!("</{}>\n", tag));
+	}
+
+	/// Write a whole element holding just 'text' on one line.
+	fn element(&mut self, tag: &str, text: &str) {
+		self.indent();
+		self.html.push_str(&format!("<{}>{}</{}>\n", tag, escape(text), tag));
+	}
+
+	fn finish(self) -> String {
+		assert!(self.depth == 0, "unclosed tags");
+		self.html
+	}
+}
+
+/// Replace the characters that mean something in HTML text with their entities.
+fn escape(text: &str) -> String {
+	let mut escaped = String::with_capacity(text.len());
+	for c in text.chars() {
+		match c {
+			'&' => escaped.push_str("&amp;"),
+			'<' => escaped.push_str("&lt;"),
+			'>' => escaped.push_str("&gt;"),
+			'"' => escaped.push_str("&quot;"),
+			_ => escaped.push(c),
+		}
+	}
+	escaped
+}
+
+#[test]
+fn test_markdown_table() {
+	let table = fizzbuzz_table(1..=5, TableFormat::Markdown);
+	let lines: Vec<&str> = table.lines().collect();
+	assert_eq!(lines[0], "| n | label |");
+	assert_eq!(lines[1], "| --- | --- |");
+	assert_eq!(lines[2], "| 1 |  |");
+	assert_eq!(lines[5], "| 4 |  |");
+	assert_eq!(lines[6], "| 5 | buzz |");
+	assert_eq!(lines.len(), 7);
+}
+
+#[test]
+fn test_html_table() {
+	let table = fizzbuzz_table(3..=3, TableFormat::Html);
+	let lines: Vec<&str> = table.lines().collect();
+	assert_eq!(lines, vec!["<table>",
+						   "  <tr>", "    <th>n</th>", "    <th>label</th>", "  </tr>",
+						   "  <tr>", "    <td>3</td>", "    <td>fizz</td>", "  </tr>",
+						   "</table>"]);
+	assert_eq!(escape("<a & \"b\">"), "&lt;a &amp; &quot;b&quot;&gt;");
+}